@@ -0,0 +1,578 @@
+// Дерево выражения как альтернатива ОПЗ для преобразований и оптимизаций
+
+use std::collections::VecDeque;
+
+use crate::error::{EvalError, ParseError};
+use crate::parser::{self, ANS, MAX_NESTING_DEPTH, Token, function_arity};
+use crate::rpn::{self, EvalOptions};
+
+/// Наибольшая глубина дерева выражения. Дерево обходится рекурсивно, так
+/// что более глубокие выражения ("----…1") вычисляются только через ОПЗ.
+pub const MAX_TREE_DEPTH: usize = 512;
+
+/// Бинарная операция.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+/// Унарная операция: префиксная ("-x", "+x") или постфиксная ("x!", "x%").
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Plus,
+    Factorial,
+    Percent,
+}
+
+/// Узел дерева выражения.
+/// Пример: "2 + 3 * 4" → BinOp(Add, Num(2), BinOp(Mul, Num(3), Num(4)))
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    // Переменная или `ans`
+    Var(String),
+    // Ссылка на результат сеанса "$N"
+    HistoryRef(usize),
+    UnOp(UnaryOp, Box<Expr>),
+    BinOp(Op, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl Op {
+    fn from_token(token: &Token) -> Option<Op> {
+        Some(match token {
+            Token::Plus => Op::Add,
+            Token::Minus => Op::Sub,
+            Token::Multiply => Op::Mul,
+            Token::Divide => Op::Div,
+            Token::Modulo => Op::Mod,
+            Token::Power => Op::Pow,
+            Token::BitAnd => Op::BitAnd,
+            Token::BitOr => Op::BitOr,
+            Token::BitXor => Op::BitXor,
+            Token::Less => Op::Less,
+            Token::LessEqual => Op::LessEqual,
+            Token::Greater => Op::Greater,
+            Token::GreaterEqual => Op::GreaterEqual,
+            Token::Equal => Op::Equal,
+            Token::NotEqual => Op::NotEqual,
+            _ => return None,
+        })
+    }
+
+    /// Возвращает токен, которым операция записывается в выражении.
+    pub fn token(self) -> Token {
+        match self {
+            Op::Add => Token::Plus,
+            Op::Sub => Token::Minus,
+            Op::Mul => Token::Multiply,
+            Op::Div => Token::Divide,
+            Op::Mod => Token::Modulo,
+            Op::Pow => Token::Power,
+            Op::BitAnd => Token::BitAnd,
+            Op::BitOr => Token::BitOr,
+            Op::BitXor => Token::BitXor,
+            Op::Less => Token::Less,
+            Op::LessEqual => Token::LessEqual,
+            Op::Greater => Token::Greater,
+            Op::GreaterEqual => Token::GreaterEqual,
+            Op::Equal => Token::Equal,
+            Op::NotEqual => Token::NotEqual,
+        }
+    }
+}
+
+impl UnaryOp {
+    fn from_token(token: &Token) -> Option<UnaryOp> {
+        Some(match token {
+            Token::UnaryMinus => UnaryOp::Neg,
+            Token::UnaryPlus => UnaryOp::Plus,
+            Token::Factorial => UnaryOp::Factorial,
+            Token::Percent => UnaryOp::Percent,
+            _ => return None,
+        })
+    }
+
+    /// Возвращает токен, которым операция записывается в выражении.
+    pub fn token(self) -> Token {
+        match self {
+            UnaryOp::Neg => Token::UnaryMinus,
+            UnaryOp::Plus => Token::UnaryPlus,
+            UnaryOp::Factorial => Token::Factorial,
+            UnaryOp::Percent => Token::Percent,
+        }
+    }
+}
+
+/// Строит дерево выражения из токенов. Приоритеты и проверка числа
+/// аргументов те же, что у `to_rpn`. Скобки вложены не глубже
+/// `MAX_NESTING_DEPTH`, а дерево — не глубже `MAX_TREE_DEPTH`.
+/// Пример: parse_ast(tokenize("-2^2")?) → UnOp(Neg, BinOp(Pow, Num(2), Num(2)))
+pub fn parse_ast(tokens: Vec<Token>) -> Result<Expr, ParseError> {
+    parser::validate_depth(&tokens, MAX_NESTING_DEPTH)?;
+    let rpn = rpn::to_rpn(tokens)?;
+    if rpn_depth(&rpn)? > MAX_TREE_DEPTH {
+        return Err(ParseError::NestingTooDeep(MAX_TREE_DEPTH));
+    }
+
+    let mut stack: Vec<Expr> = Vec::new();
+    let missing = |token: &Token| {
+        ParseError::InvalidExpression(format!("Недостаточно операндов для операции '{}'", token))
+    };
+
+    for token in rpn {
+        let expr = match &token {
+            Token::Number(num) => Expr::Num(*num),
+            Token::Variable(name) => Expr::Var(name.clone()),
+            Token::Ans => Expr::Var(ANS.to_string()),
+            Token::HistoryRef(n) => Expr::HistoryRef(*n),
+            Token::Function(name) => {
+                let arity = function_arity(name);
+                if stack.len() < arity {
+                    return Err(missing(&token));
+                }
+                let args = stack.split_off(stack.len() - arity);
+                Expr::Call(name.clone(), args)
+            }
+            _ => {
+                if let Some(op) = UnaryOp::from_token(&token) {
+                    let operand = stack.pop().ok_or_else(|| missing(&token))?;
+                    Expr::UnOp(op, Box::new(operand))
+                } else if let Some(op) = Op::from_token(&token) {
+                    let (Some(right), Some(left)) = (stack.pop(), stack.pop()) else {
+                        return Err(missing(&token));
+                    };
+                    Expr::BinOp(op, Box::new(left), Box::new(right))
+                } else {
+                    return Err(ParseError::InvalidExpression(format!(
+                        "Неподдерживаемый токен: '{}'",
+                        token
+                    )));
+                }
+            }
+        };
+        stack.push(expr);
+    }
+
+    match (stack.pop(), stack.is_empty()) {
+        (Some(expr), true) => Ok(expr),
+        (Some(_), _) => Err(ParseError::InvalidExpression(
+            "В стеке остались лишние числа".to_string(),
+        )),
+        (_, _) => Err(ParseError::InvalidExpression(
+            "Стек пуст после вычислений".to_string(),
+        )),
+    }
+}
+
+/// Проверяет, что каждой операции хватает операндов, с теми же ошибками,
+/// что и `parse_ast`, но без построения дерева: вместо узлов считается
+/// только глубина стека, так что длинные цепочки вроде "----1" не
+/// переполняют стек вызовов.
+pub fn check_operands(tokens: Vec<Token>) -> Result<(), ParseError> {
+    rpn_depth(&rpn::to_rpn(tokens)?).map(|_| ())
+}
+
+// Проверяет операнды ОПЗ и возвращает глубину дерева, которое из неё
+// получится. Вместо узлов хранятся только их глубины
+fn rpn_depth(rpn: &VecDeque<Token>) -> Result<usize, ParseError> {
+    let mut depths: Vec<usize> = Vec::new();
+    let mut max_depth = 0;
+    let missing = |token: &Token| {
+        ParseError::InvalidExpression(format!("Недостаточно операндов для операции '{}'", token))
+    };
+
+    for token in rpn {
+        let arity = match token {
+            Token::Number(_) | Token::Variable(_) | Token::Ans | Token::HistoryRef(_) => 0,
+            Token::Function(name) => function_arity(name),
+            _ if UnaryOp::from_token(token).is_some() => 1,
+            _ if Op::from_token(token).is_some() => 2,
+            _ => {
+                return Err(ParseError::InvalidExpression(format!(
+                    "Неподдерживаемый токен: '{}'",
+                    token
+                )));
+            }
+        };
+        if depths.len() < arity {
+            return Err(missing(token));
+        }
+        // Операция снимает свои операнды и кладёт узел на уровень выше них
+        let operands = depths.split_off(depths.len() - arity);
+        let depth = operands.into_iter().max().unwrap_or(0) + 1;
+        max_depth = max_depth.max(depth);
+        depths.push(depth);
+    }
+
+    match depths.len() {
+        1 => Ok(max_depth),
+        0 => Err(ParseError::InvalidExpression(
+            "Стек пуст после вычислений".to_string(),
+        )),
+        _ => Err(ParseError::InvalidExpression(
+            "В стеке остались лишние числа".to_string(),
+        )),
+    }
+}
+
+/// Вычисляет дерево выражения с настройками по умолчанию.
+/// Результаты и ошибки совпадают с `eval_rpn`.
+pub fn eval_ast(expr: &Expr) -> Result<f64, EvalError> {
+    eval_ast_with(expr, &EvalOptions::default())
+}
+
+/// Вычисляет дерево выражения с заданными настройками. Дерево глубже
+/// `MAX_TREE_DEPTH` не вычисляется.
+pub fn eval_ast_with(expr: &Expr, options: &EvalOptions) -> Result<f64, EvalError> {
+    eval_node(expr, options, 1)
+}
+
+// Вычисляет узел на глубине depth
+fn eval_node(expr: &Expr, options: &EvalOptions, depth: usize) -> Result<f64, EvalError> {
+    if depth > MAX_TREE_DEPTH {
+        return Err(EvalError::InvalidExpression(format!(
+            "Дерево выражения глубже {} уровней",
+            MAX_TREE_DEPTH
+        )));
+    }
+    let eval = |e: &Expr| eval_node(e, options, depth + 1);
+
+    // Операнды вычисляются рекурсивно, а сама операция — так же, как в ОПЗ
+    let (token, mut stack) = match expr {
+        Expr::Num(num) => return Ok(*num),
+        Expr::Var(name) if name == ANS => (Token::Ans, Vec::new()),
+        Expr::Var(name) => (Token::Variable(name.clone()), Vec::new()),
+        Expr::HistoryRef(n) => (Token::HistoryRef(*n), Vec::new()),
+        Expr::UnOp(op, operand) => (op.token(), vec![eval(operand)?]),
+        Expr::BinOp(op, left, right) => {
+            let left = eval(left)?;
+            // Процент справа от "+" или "-" в режиме percent_of, как в ОПЗ
+            let right = match &**right {
+                Expr::UnOp(UnaryOp::Percent, percent) if percent_of_applies(*op, options) => {
+                    rpn::percent_of(left, eval(percent)?, options)?
+                }
+                right => eval(right)?,
+            };
+            (op.token(), vec![left, right])
+        }
+        Expr::Call(name, args) => (
+            Token::Function(name.clone()),
+            args.iter().map(eval).collect::<Result<_, _>>()?,
+        ),
+    };
+
+    rpn::apply(&token, &mut stack, options)?;
+    match stack[..] {
+        [result] => Ok(result),
+        _ => Err(EvalError::InvalidExpression(format!(
+            "Неверное число аргументов для '{}'",
+            token
+        ))),
+    }
+}
+
+/// Сворачивает поддеревья без переменных в числа с настройками по умолчанию.
+/// Пример: "2 + 3 * 4" → Num(14), "x * (2 + 3)" → BinOp(Mul, Var(x), Num(5))
+pub fn fold_constants(expr: Expr) -> Expr {
+    fold_constants_with(expr, &EvalOptions::default())
+}
+
+/// Сворачивает константные поддеревья с заданными настройками: от них
+/// зависит, например, результат "sin(90)". Поддерево, вычисление которого
+/// даёт ошибку ("1 / 0"), не сворачивается, и ошибка возникает при вычислении.
+pub fn fold_constants_with(expr: Expr, options: &EvalOptions) -> Expr {
+    let fold = |e: Box<Expr>| Box::new(fold_constants_with(*e, options));
+    let folded = match expr {
+        Expr::Num(_) | Expr::Var(_) | Expr::HistoryRef(_) => return expr,
+        Expr::UnOp(op, operand) => Expr::UnOp(op, fold(operand)),
+        // "20%" справа от "+" или "-" в режиме percent_of не сворачивается
+        // в 0.2: его значение зависит от левого операнда
+        Expr::BinOp(op, left, right) => match *right {
+            Expr::UnOp(UnaryOp::Percent, percent) if percent_of_applies(op, options) => {
+                Expr::BinOp(
+                    op,
+                    fold(left),
+                    Box::new(Expr::UnOp(UnaryOp::Percent, fold(percent))),
+                )
+            }
+            right => Expr::BinOp(op, fold(left), fold(Box::new(right))),
+        },
+        Expr::Call(name, args) => Expr::Call(
+            name,
+            args.into_iter()
+                .map(|arg| fold_constants_with(arg, options))
+                .collect(),
+        ),
+    };
+
+    let is_constant = match &folded {
+        Expr::UnOp(_, operand) => matches!(**operand, Expr::Num(_)),
+        Expr::BinOp(op, left, right) => {
+            matches!(**left, Expr::Num(_))
+                && match &**right {
+                    Expr::Num(_) => true,
+                    Expr::UnOp(UnaryOp::Percent, percent) => {
+                        percent_of_applies(*op, options) && matches!(**percent, Expr::Num(_))
+                    }
+                    _ => false,
+                }
+        }
+        // rand даёт новое число при каждом вычислении
+        Expr::Call(name, args) => {
+            name != "rand" && args.iter().all(|arg| matches!(arg, Expr::Num(_)))
+        }
+        Expr::Num(_) | Expr::Var(_) | Expr::HistoryRef(_) => false,
+    };
+    match is_constant.then(|| eval_ast_with(&folded, options)) {
+        Some(Ok(value)) => Expr::Num(value),
+        _ => folded,
+    }
+}
+
+// Процент справа от операции берётся от левого операнда
+fn percent_of_applies(op: Op, options: &EvalOptions) -> bool {
+    options.percent_of && matches!(op, Op::Add | Op::Sub)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tokenize;
+    use crate::rpn::{eval_rpn, to_rpn};
+
+    #[test]
+    fn test_check_operands_matches_parse_ast() {
+        for input in [
+            "2 + 3 * 4",
+            "-max(1, 2)!",
+            "1 +",
+            "min(1)",
+            "2 3",
+            "sqrt(4) * ans",
+        ] {
+            let tokens = tokenize(input).unwrap();
+            assert_eq!(
+                check_operands(tokens.clone()),
+                parse_ast(tokens).map(|_| ()),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_ast_shape() {
+        let expr = parse_ast(tokenize("2 + 3 * 4").unwrap()).unwrap();
+        assert_eq!(
+            expr,
+            Expr::BinOp(
+                Op::Add,
+                Box::new(Expr::Num(2.0)),
+                Box::new(Expr::BinOp(
+                    Op::Mul,
+                    Box::new(Expr::Num(3.0)),
+                    Box::new(Expr::Num(4.0))
+                ))
+            )
+        );
+
+        let expr = parse_ast(tokenize("-max(1, 2)!").unwrap()).unwrap();
+        assert_eq!(
+            expr,
+            Expr::UnOp(
+                UnaryOp::Neg,
+                Box::new(Expr::UnOp(
+                    UnaryOp::Factorial,
+                    Box::new(Expr::Call(
+                        "max".to_string(),
+                        vec![Expr::Num(1.0), Expr::Num(2.0)]
+                    ))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn test_eval_ast_matches_rpn() {
+        for input in [
+            "2 + 3 * 4",
+            "2^3^2",
+            "-2^2",
+            "(1 + 2) * (3 - 4) / 5",
+            "10 % 3 + 50 + 10%",
+            "sqrt(16) + max(2, 7) - root(27, 3)",
+            "3! * sin(0) + cos(0)",
+            "6 & 3 | 8 ^^ 1",
+            "1 + 1 == 2",
+            "1 / 0",
+            "sqrt(-1)",
+        ] {
+            let tokens = tokenize(input).unwrap();
+            let expr = parse_ast(tokens.clone()).unwrap();
+            let rpn = to_rpn(tokens).unwrap();
+            assert_eq!(eval_ast(&expr), eval_rpn(&rpn), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let parse = |input: &str| {
+            let vars = std::collections::HashMap::from([("x".to_string(), 0.0)]);
+            parse_ast(crate::parser::tokenize_with_vars(input, &vars).unwrap()).unwrap()
+        };
+        let num = |n: f64| Box::new(Expr::Num(n));
+        let x = || Box::new(Expr::Var("x".to_string()));
+
+        assert_eq!(fold_constants(parse("2 + 3 * 4")), Expr::Num(14.0));
+        assert_eq!(fold_constants(parse("max(2, 3)! - 1")), Expr::Num(5.0));
+
+        // Поддеревья с переменной не сворачиваются
+        assert_eq!(
+            fold_constants(parse("2 + 3 * x")),
+            Expr::BinOp(
+                Op::Add,
+                num(2.0),
+                Box::new(Expr::BinOp(Op::Mul, num(3.0), x()))
+            )
+        );
+        assert_eq!(
+            fold_constants(parse("x * (2 + 3)")),
+            Expr::BinOp(Op::Mul, x(), num(5.0))
+        );
+        assert_eq!(
+            fold_constants(parse("sqrt(x + 2^2)")),
+            Expr::Call(
+                "sqrt".to_string(),
+                vec![Expr::BinOp(Op::Add, x(), num(4.0))]
+            )
+        );
+    }
+
+    #[test]
+    fn test_percent_of_matches_rpn() {
+        let options = EvalOptions {
+            percent_of: true,
+            ..EvalOptions::default()
+        };
+        for input in [
+            "100 + 20%",
+            "100 - 20%",
+            "(50 + 50) + 10% * 2",
+            "200 * 15%",
+            "20%",
+            "1 + (100 + 20%)",
+        ] {
+            let tokens = tokenize(input).unwrap();
+            let from_rpn = rpn::eval_rpn_with(&to_rpn(tokens.clone()).unwrap(), &options);
+            let expr = parse_ast(tokens).unwrap();
+            assert_eq!(eval_ast_with(&expr, &options), from_rpn, "input: {}", input);
+            assert_eq!(
+                eval_ast_with(&fold_constants_with(expr, &options), &options),
+                from_rpn,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_history_ref_error_matches_rpn() {
+        let tokens = tokenize("$1 + 1").unwrap();
+        let expr = parse_ast(tokens.clone()).unwrap();
+        assert_eq!(
+            expr,
+            Expr::BinOp(
+                Op::Add,
+                Box::new(Expr::HistoryRef(1)),
+                Box::new(Expr::Num(1.0))
+            )
+        );
+        assert_eq!(eval_ast(&expr), eval_rpn(&to_rpn(tokens).unwrap()));
+        assert!(matches!(
+            eval_ast(&expr),
+            Err(EvalError::InvalidExpression(_))
+        ));
+    }
+
+    #[test]
+    fn test_depth_limits() {
+        // Глубина дерева в пределах лимита вычисляется
+        let deep = "-".repeat(MAX_TREE_DEPTH - 1) + "1";
+        let expr = parse_ast(tokenize(&deep).unwrap()).unwrap();
+        assert_eq!(eval_ast(&expr), Ok(-1.0));
+        assert_eq!(fold_constants(expr), Expr::Num(-1.0));
+
+        let deeper = "-".repeat(MAX_TREE_DEPTH) + "1";
+        assert_eq!(
+            parse_ast(tokenize(&deeper).unwrap()),
+            Err(ParseError::NestingTooDeep(MAX_TREE_DEPTH))
+        );
+
+        // Скобки проверяются так же, как при вычислении через ОПЗ
+        let nested = "(".repeat(MAX_NESTING_DEPTH + 1) + "1" + &")".repeat(MAX_NESTING_DEPTH + 1);
+        assert_eq!(
+            parse_ast(tokenize(&nested).unwrap()),
+            Err(ParseError::NestingTooDeep(MAX_NESTING_DEPTH))
+        );
+
+        // Дерево, построенное вручную, тоже не обходится глубже лимита
+        let mut expr = Expr::Num(1.0);
+        for _ in 0..MAX_TREE_DEPTH {
+            expr = Expr::UnOp(UnaryOp::Neg, Box::new(expr));
+        }
+        assert!(matches!(
+            eval_ast(&expr),
+            Err(EvalError::InvalidExpression(_))
+        ));
+    }
+
+    #[test]
+    fn test_fold_constants_defers_errors() {
+        // Деление на ноль остаётся в дереве и обнаруживается при вычислении
+        let expr = fold_constants(parse_ast(tokenize("1 / 0 + 2 * 3").unwrap()).unwrap());
+        assert_eq!(
+            expr,
+            Expr::BinOp(
+                Op::Add,
+                Box::new(Expr::BinOp(
+                    Op::Div,
+                    Box::new(Expr::Num(1.0)),
+                    Box::new(Expr::Num(0.0))
+                )),
+                Box::new(Expr::Num(6.0))
+            )
+        );
+        assert_eq!(eval_ast(&expr), Err(EvalError::DivideByZero));
+
+        let expr = fold_constants(parse_ast(tokenize("sqrt(-1)").unwrap()).unwrap());
+        assert!(matches!(eval_ast(&expr), Err(EvalError::DomainError(_))));
+    }
+
+    #[test]
+    fn test_parse_ast_errors() {
+        assert_eq!(
+            parse_ast(tokenize("(1 + 2").unwrap()),
+            Err(ParseError::UnmatchedParens)
+        );
+        assert!(matches!(
+            parse_ast(tokenize("1 +").unwrap()),
+            Err(ParseError::InvalidExpression(_))
+        ));
+    }
+}
@@ -1,56 +1,486 @@
 use std::error::Error;
 use std::fmt;
+use std::num::ParseFloatError;
+use std::ops::Range;
 
-// Определяем перечисление для ошибок калькулятора
-#[derive(Debug, PartialEq)]
+// Определяем перечисление для ошибок калькулятора: ошибка разбора или
+// ошибка вычисления. Текст и код ошибки берутся из вложенной ошибки.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", content = "value")
+)]
 pub enum CalcError {
+    Parse(ParseError),
+    Eval(EvalError),
+}
+
+/// Ошибка разбора: выражение записано некорректно и не может быть вычислено.
+/// Пример: tokenize("2 @ 3") → Err(ParseError::InvalidTokenAt { ch: '@', pos: 2 })
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", content = "value")
+)]
+pub enum ParseError {
     InvalidToken(String),
+    InvalidTokenAt {
+        ch: char,
+        pos: usize,
+    },
+    // Число не удалось разобрать; исходная ошибка доступна через Error::source
+    InvalidNumber {
+        literal: String,
+        #[cfg_attr(feature = "serde", serde(with = "parse_float_error"))]
+        source: ParseFloatError,
+    },
     UnmatchedParens,
+    UnmatchedParensAt(usize),
+    InvalidExpression(String),
+    UnknownVariable(String),
+    NestingTooDeep(usize),
+}
+
+/// Ошибка вычисления: выражение разобрано, но его значение получить нельзя.
+/// Пример: eval_rpn("1 0 /") → Err(EvalError::DivideByZero)
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", content = "value")
+)]
+pub enum EvalError {
+    InvalidToken(String),
     DivideByZero,
     InvalidExpression(String),
+    DomainError(String),
+    NotFinite(String),
+    UnknownVariable(String),
+    StepLimitExceeded(usize),
+}
+
+// Ошибка вместе с диапазоном байтов выражения, к которому она относится
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedError {
+    pub error: CalcError,
+    pub span: Range<usize>,
+}
+
+// Язык сообщений об ошибках
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Language {
+    #[default]
+    Russian,
+    English,
+}
+
+impl CalcError {
+    /// Возвращает стабильный код ошибки, не зависящий от текста сообщения.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CalcError::Parse(error) => error.code(),
+            CalcError::Eval(error) => error.code(),
+        }
+    }
+
+    /// Возвращает текст ошибки на выбранном языке.
+    pub fn message(&self, lang: Language) -> String {
+        match self {
+            CalcError::Parse(error) => error.message(lang),
+            CalcError::Eval(error) => error.message(lang),
+        }
+    }
+}
+
+impl ParseError {
+    /// Возвращает стабильный код ошибки, не зависящий от текста сообщения.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::InvalidToken(_)
+            | ParseError::InvalidTokenAt { .. }
+            | ParseError::InvalidNumber { .. } => "INVALID_TOKEN",
+            ParseError::UnmatchedParens | ParseError::UnmatchedParensAt(_) => "UNMATCHED_PARENS",
+            ParseError::InvalidExpression(_) => "INVALID_EXPRESSION",
+            ParseError::UnknownVariable(_) => "UNKNOWN_VARIABLE",
+            ParseError::NestingTooDeep(_) => "NESTING_TOO_DEEP",
+        }
+    }
+
+    /// Возвращает текст ошибки на выбранном языке.
+    pub fn message(&self, lang: Language) -> String {
+        match lang {
+            Language::Russian => match self {
+                ParseError::InvalidToken(token) => format!("Некорректный символ: {}", token),
+                ParseError::InvalidTokenAt { ch, pos } => {
+                    format!("Некорректный символ '{}' в позиции {}", ch, pos)
+                }
+                ParseError::InvalidNumber { literal, .. } => {
+                    format!("Некорректное число: '{}'.", literal)
+                }
+                ParseError::UnmatchedParens => "Не совпадают скобки.".to_owned(),
+                ParseError::UnmatchedParensAt(i) => format!("Не совпадают скобки (токен {}).", i),
+                ParseError::InvalidExpression(expr) => format!("Некорректное выражение: {}", expr),
+                ParseError::UnknownVariable(name) => format!("Неизвестная переменная: '{}'", name),
+                ParseError::NestingTooDeep(max) => {
+                    format!("Слишком глубокая вложенность скобок (больше {}).", max)
+                }
+            },
+            Language::English => match self {
+                ParseError::InvalidToken(token) => format!("Invalid token: {}", token),
+                ParseError::InvalidTokenAt { ch, pos } => {
+                    format!("Invalid character '{}' at position {}", ch, pos)
+                }
+                ParseError::InvalidNumber { literal, source } => {
+                    format!("Invalid number '{}': {}.", literal, source)
+                }
+                ParseError::UnmatchedParens => "Unmatched parentheses.".to_owned(),
+                ParseError::UnmatchedParensAt(i) => {
+                    format!("Unmatched parentheses (token {}).", i)
+                }
+                ParseError::InvalidExpression(expr) => format!("Invalid expression: {}", expr),
+                ParseError::UnknownVariable(name) => format!("Unknown variable: '{}'", name),
+                ParseError::NestingTooDeep(max) => {
+                    format!("Parentheses nested too deeply (more than {}).", max)
+                }
+            },
+        }
+    }
+}
+
+impl EvalError {
+    /// Возвращает стабильный код ошибки, не зависящий от текста сообщения.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EvalError::InvalidToken(_) => "INVALID_TOKEN",
+            EvalError::DivideByZero => "DIVIDE_BY_ZERO",
+            EvalError::InvalidExpression(_) => "INVALID_EXPRESSION",
+            EvalError::DomainError(_) => "DOMAIN_ERROR",
+            EvalError::NotFinite(_) => "NOT_FINITE",
+            EvalError::UnknownVariable(_) => "UNKNOWN_VARIABLE",
+            EvalError::StepLimitExceeded(_) => "STEP_LIMIT_EXCEEDED",
+        }
+    }
+
+    /// Возвращает текст ошибки на выбранном языке.
+    pub fn message(&self, lang: Language) -> String {
+        match lang {
+            Language::Russian => match self {
+                EvalError::InvalidToken(token) => format!("Некорректный символ: {}", token),
+                EvalError::DivideByZero => "Деление на 0.".to_owned(),
+                EvalError::InvalidExpression(expr) => format!("Некорректное выражение: {}", expr),
+                EvalError::DomainError(msg) => format!("Ошибка области определения: {}", msg),
+                EvalError::NotFinite(msg) => {
+                    format!("Результат не является конечным числом: {}", msg)
+                }
+                EvalError::UnknownVariable(name) => format!("Неизвестная переменная: '{}'", name),
+                EvalError::StepLimitExceeded(max) => {
+                    format!("Превышен лимит операций при вычислении ({}).", max)
+                }
+            },
+            Language::English => match self {
+                EvalError::InvalidToken(token) => format!("Invalid token: {}", token),
+                EvalError::DivideByZero => "Division by zero.".to_owned(),
+                EvalError::InvalidExpression(expr) => format!("Invalid expression: {}", expr),
+                EvalError::DomainError(msg) => format!("Domain error: {}", msg),
+                EvalError::NotFinite(msg) => format!("Result is not a finite number: {}", msg),
+                EvalError::UnknownVariable(name) => format!("Unknown variable: '{}'", name),
+                EvalError::StepLimitExceeded(max) => {
+                    format!("Evaluation step limit exceeded ({}).", max)
+                }
+            },
+        }
+    }
 }
 
 // Реализуем Display для CalcError для удобного вывода ошибок
 impl fmt::Display for CalcError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let message = match self {
-            CalcError::InvalidToken(token) => format!("Некорректный символ: {}", token),
-            CalcError::UnmatchedParens => "Не совпадают скобки.".to_owned(),
-            CalcError::DivideByZero => "Деление на 0.".to_owned(),
-            CalcError::InvalidExpression(expr) => format!("Некорректное выражение: {}", expr),
-        };
-
-        write!(f, "{}", message)
+        write!(f, "{}", self.message(Language::default()))
     }
 }
 
 // Реализуем Error для CalcError для использования в контексте ошибок
-impl Error for CalcError {}
+impl Error for CalcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CalcError::Parse(error) => error.source(),
+            CalcError::Eval(error) => error.source(),
+        }
+    }
+}
+
+impl From<ParseError> for CalcError {
+    fn from(error: ParseError) -> Self {
+        CalcError::Parse(error)
+    }
+}
+
+impl From<EvalError> for CalcError {
+    fn from(error: EvalError) -> Self {
+        CalcError::Eval(error)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message(Language::default()))
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::InvalidNumber { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message(Language::default()))
+    }
+}
+
+impl Error for EvalError {}
+
+// ParseFloatError не поддерживает serde: сохраняем его текст, а при чтении
+// восстанавливаем ошибку того же вида разбором пустой или некорректной строки
+#[cfg(feature = "serde")]
+mod parse_float_error {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::num::ParseFloatError;
+
+    pub fn serialize<S: Serializer>(error: &ParseFloatError, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(error)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<ParseFloatError, D::Error> {
+        let message = String::deserialize(d)?;
+        let empty = "".parse::<f64>().unwrap_err();
+        if message == empty.to_string() {
+            Ok(empty)
+        } else {
+            Ok("?".parse::<f64>().unwrap_err())
+        }
+    }
+}
+
+impl fmt::Display for SpannedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (позиция {})", self.error, self.span.start)
+    }
+}
+
+impl Error for SpannedError {}
 
 // Модуль для тестов
 #[cfg(test)]
 mod tests {
-    use super::CalcError;
+    use super::{CalcError, EvalError, Language, ParseError, SpannedError};
+
+    #[test]
+    fn test_calcerror_clone() {
+        let error = CalcError::Eval(EvalError::DomainError("sqrt(-1)".to_string()));
+        assert_eq!(error.clone(), error);
+    }
+
+    #[test]
+    fn test_calcerror_invalid_number_source() {
+        use std::error::Error;
+
+        let parse_error = "1.2.3".parse::<f64>().unwrap_err();
+        let error = CalcError::Parse(ParseError::InvalidNumber {
+            literal: "1.2.3".to_string(),
+            source: parse_error.clone(),
+        });
+        assert_eq!(error.code(), "INVALID_TOKEN");
+        assert_eq!(error.to_string(), "Некорректное число: '1.2.3'.");
+        assert_eq!(
+            error.message(Language::English),
+            "Invalid number '1.2.3': invalid float literal."
+        );
+        assert_eq!(
+            error.source().map(|e| e.to_string()),
+            Some(parse_error.to_string())
+        );
+        assert!(CalcError::Eval(EvalError::DivideByZero).source().is_none());
+    }
 
     #[test]
     fn test_calcerror_invalid_token() {
-        let error = CalcError::InvalidToken("abc".to_string());
+        let error = CalcError::Parse(ParseError::InvalidToken("abc".to_string()));
         assert_eq!(format!("{}", error), "Некорректный символ: abc");
     }
 
+    #[test]
+    fn test_calcerror_invalid_token_at() {
+        let error = CalcError::Parse(ParseError::InvalidTokenAt { ch: 'a', pos: 4 });
+        assert_eq!(format!("{}", error), "Некорректный символ 'a' в позиции 4");
+    }
+
     #[test]
     fn test_calcerror_unmatched_parens() {
-        let error = CalcError::UnmatchedParens;
+        let error = CalcError::Parse(ParseError::UnmatchedParens);
         assert_eq!(format!("{}", error), "Не совпадают скобки.");
     }
+
+    #[test]
+    fn test_calcerror_unmatched_parens_at() {
+        let error = CalcError::Parse(ParseError::UnmatchedParensAt(3));
+        assert_eq!(format!("{}", error), "Не совпадают скобки (токен 3).");
+    }
+
     #[test]
     fn test_calcerror_divide_by_zero() {
-        let error = CalcError::DivideByZero;
+        let error = CalcError::Eval(EvalError::DivideByZero);
         assert_eq!(format!("{}", error), "Деление на 0.");
     }
     #[test]
     fn test_calcerror_invalid_expression() {
-        let error = CalcError::InvalidExpression("1 + 2 *".to_string());
+        let error = CalcError::Parse(ParseError::InvalidExpression("1 + 2 *".to_string()));
         assert_eq!(format!("{}", error), "Некорректное выражение: 1 + 2 *");
     }
+    #[test]
+    fn test_calcerror_domain_error() {
+        let error = CalcError::Eval(EvalError::DomainError("sqrt(-1)".to_string()));
+        assert_eq!(format!("{}", error), "Ошибка области определения: sqrt(-1)");
+    }
+
+    #[test]
+    fn test_calcerror_not_finite() {
+        let error = CalcError::Eval(EvalError::NotFinite("переполнение".to_string()));
+        assert_eq!(
+            format!("{}", error),
+            "Результат не является конечным числом: переполнение"
+        );
+    }
+
+    #[test]
+    fn test_calcerror_unknown_variable() {
+        let error = CalcError::Parse(ParseError::UnknownVariable("z".to_string()));
+        assert_eq!(format!("{}", error), "Неизвестная переменная: 'z'");
+        assert_eq!(error.message(Language::English), "Unknown variable: 'z'");
+    }
+
+    #[test]
+    fn test_calcerror_message_russian_matches_display() {
+        let error = CalcError::Parse(ParseError::UnmatchedParens);
+        assert_eq!(error.message(Language::Russian), format!("{}", error));
+    }
+
+    #[test]
+    fn test_calcerror_message_english() {
+        let error = CalcError::Eval(EvalError::DivideByZero);
+        assert_eq!(error.message(Language::English), "Division by zero.");
+
+        let error = CalcError::Parse(ParseError::UnmatchedParens);
+        assert_eq!(error.message(Language::English), "Unmatched parentheses.");
+
+        let error = CalcError::Parse(ParseError::InvalidTokenAt { ch: 'a', pos: 4 });
+        assert_eq!(
+            error.message(Language::English),
+            "Invalid character 'a' at position 4"
+        );
+
+        let error = CalcError::Parse(ParseError::InvalidToken("1.2.3".to_string()));
+        assert_eq!(error.message(Language::English), "Invalid token: 1.2.3");
+
+        let error = CalcError::Parse(ParseError::InvalidExpression("1 + 2 *".to_string()));
+        assert_eq!(
+            error.message(Language::English),
+            "Invalid expression: 1 + 2 *"
+        );
+
+        let error = CalcError::Eval(EvalError::DomainError("sqrt(-1)".to_string()));
+        assert_eq!(error.message(Language::English), "Domain error: sqrt(-1)");
+    }
+
+    #[test]
+    fn test_calcerror_code() {
+        let s = String::new;
+        assert_eq!(
+            CalcError::Parse(ParseError::InvalidToken(s())).code(),
+            "INVALID_TOKEN"
+        );
+        assert_eq!(
+            CalcError::Parse(ParseError::InvalidTokenAt { ch: 'a', pos: 0 }).code(),
+            "INVALID_TOKEN"
+        );
+        assert_eq!(
+            CalcError::Parse(ParseError::UnmatchedParens).code(),
+            "UNMATCHED_PARENS"
+        );
+        assert_eq!(
+            CalcError::Parse(ParseError::UnmatchedParensAt(0)).code(),
+            "UNMATCHED_PARENS"
+        );
+        assert_eq!(
+            CalcError::Eval(EvalError::DivideByZero).code(),
+            "DIVIDE_BY_ZERO"
+        );
+        assert_eq!(
+            CalcError::Parse(ParseError::InvalidExpression(s())).code(),
+            "INVALID_EXPRESSION"
+        );
+        assert_eq!(
+            CalcError::Eval(EvalError::DomainError(s())).code(),
+            "DOMAIN_ERROR"
+        );
+        assert_eq!(
+            CalcError::Eval(EvalError::NotFinite(s())).code(),
+            "NOT_FINITE"
+        );
+        assert_eq!(
+            CalcError::Parse(ParseError::UnknownVariable(s())).code(),
+            "UNKNOWN_VARIABLE"
+        );
+        assert_eq!(
+            CalcError::Parse(ParseError::NestingTooDeep(1)).code(),
+            "NESTING_TOO_DEEP"
+        );
+        assert_eq!(
+            CalcError::Eval(EvalError::StepLimitExceeded(1)).code(),
+            "STEP_LIMIT_EXCEEDED"
+        );
+    }
+
+    #[test]
+    fn test_parse_and_eval_errors_convert() {
+        let error = ParseError::UnmatchedParensAt(3);
+        assert_eq!(error.to_string(), "Не совпадают скобки (токен 3).");
+        assert_eq!(
+            CalcError::from(error),
+            CalcError::Parse(ParseError::UnmatchedParensAt(3))
+        );
+
+        let error = EvalError::DivideByZero;
+        assert_eq!(error.to_string(), "Деление на 0.");
+        assert_eq!(CalcError::from(error).code(), "DIVIDE_BY_ZERO");
+
+        let error = ParseError::InvalidNumber {
+            literal: "1.2.3".to_string(),
+            source: "1.2.3".parse::<f64>().unwrap_err(),
+        };
+        assert_eq!(error.to_string(), "Некорректное число: '1.2.3'.");
+        assert_eq!(CalcError::from(error).code(), "INVALID_TOKEN");
+
+        // Одноимённые варианты двух категорий выводятся одинаково
+        let parse = CalcError::from(ParseError::UnknownVariable("z".to_string()));
+        let eval = CalcError::from(EvalError::UnknownVariable("z".to_string()));
+        assert_ne!(parse, eval);
+        assert_eq!(parse.code(), eval.code());
+        assert_eq!(parse.to_string(), eval.to_string());
+    }
+
+    #[test]
+    fn test_spanned_error_display() {
+        let error = SpannedError {
+            error: CalcError::Eval(EvalError::DivideByZero),
+            span: 2..3,
+        };
+        assert_eq!(format!("{}", error), "Деление на 0. (позиция 2)");
+    }
 }
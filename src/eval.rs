@@ -0,0 +1,423 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::Deref;
+
+use crate::ast;
+use crate::error::{CalcError, ParseError, SpannedError};
+use crate::output;
+use crate::parser::{self, Lexer, Spanned, Token};
+use crate::rpn::{self, AngleMode, EvalOptions, RpnBuffers};
+
+/// Вычисляет выражение и возвращает результат.
+/// Пример: evaluate("2 + 2") → Ok(Number(4.0))
+pub fn evaluate(input: &str) -> Result<Number, CalcError> {
+    evaluate_with_ans(input, None).map(Number)
+}
+
+/// Число-результат вычисления: `*result` даёт f64, а при выводе число
+/// форматируется как в калькуляторе.
+/// Пример: format!("{}", Number(0.1 + 0.2)) → "0.3"
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Number(pub f64);
+
+impl Deref for Number {
+    type Target = f64;
+
+    fn deref(&self) -> &f64 {
+        &self.0
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", output::format_number(self.0))
+    }
+}
+
+impl From<Number> for f64 {
+    fn from(number: Number) -> Self {
+        number.0
+    }
+}
+
+impl PartialEq<f64> for Number {
+    fn eq(&self, other: &f64) -> bool {
+        self.0 == *other
+    }
+}
+
+/// Результат вычисления вместе с его строковым представлением.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalResult {
+    pub value: f64,
+    // Число, отформатированное как в выводе калькулятора: "2.5", "0.3"
+    pub display: String,
+}
+
+/// Вычисляет выражение и возвращает значение вместе с отформатированной строкой.
+/// Пример: evaluate_detailed("10 / 4") → Ok(EvalResult { value: 2.5, display: "2.5" })
+pub fn evaluate_detailed(input: &str) -> Result<EvalResult, CalcError> {
+    let value = *evaluate(input)?;
+    Ok(EvalResult {
+        value,
+        display: output::format_number(value),
+    })
+}
+
+/// Вычисляет несколько выражений, разделённых ";" вне скобок, и возвращает
+/// результаты по порядку. Пустые выражения не допускаются.
+/// Пример: evaluate_sequence("1+1; 2*3; 4-1") → Ok(vec![2.0, 6.0, 3.0])
+pub fn evaluate_sequence(input: &str) -> Result<Vec<f64>, CalcError> {
+    evaluate_sequence_with(input, &EvalOptions::default())
+}
+
+/// Вычисляет последовательность выражений через ";" с заданными настройками.
+/// Пример: evaluate_sequence_with("90d30m; 1d", { dms: true }) → Ok(vec![90.5, 1.0])
+pub fn evaluate_sequence_with(input: &str, options: &EvalOptions) -> Result<Vec<f64>, CalcError> {
+    let mut segments = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ';' if depth == 0 => {
+                segments.push(&input[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    segments.push(&input[start..]);
+
+    segments
+        .into_iter()
+        .map(|segment| {
+            if segment.trim().is_empty() {
+                return Err(ParseError::InvalidExpression(
+                    "Пустое выражение в последовательности".to_string(),
+                )
+                .into());
+            }
+            run(segment, None, &[], None, options).map_err(|e| e.error)
+        })
+        .collect()
+}
+
+/// Вычисляет каждое выражение независимо, не останавливаясь на ошибках.
+/// Буферы разбора переиспользуются между выражениями.
+/// Пример: evaluate_many(&["1 + 1", "1 / 0"]) → [Ok(2.0), Err(DivideByZero)]
+pub fn evaluate_many(inputs: &[&str]) -> Vec<Result<f64, CalcError>> {
+    let options = EvalOptions::default();
+    let mut buffers = RpnBuffers::default();
+    inputs
+        .iter()
+        .map(|input| run_in(input, None, &[], None, &options, &mut buffers).map_err(|e| e.error))
+        .collect()
+}
+
+/// Вычисляет выражение, подставляя `ans` — результат предыдущего вычисления.
+/// Пример: evaluate_with_ans("ans * 2", Some(3.0)) → Ok(6.0)
+pub fn evaluate_with_ans(input: &str, ans: Option<f64>) -> Result<f64, CalcError> {
+    run(input, ans, &[], None, &EvalOptions::default()).map_err(|e| e.error)
+}
+
+/// Вычисляет выражение, подставляя значения переменных из `vars`.
+/// Пример: evaluate_with_vars("x * y + 1", {x: 2, y: 3}) → Ok(7.0)
+pub fn evaluate_with_vars(input: &str, vars: &HashMap<String, f64>) -> Result<f64, CalcError> {
+    run(input, None, &[], Some(vars), &EvalOptions::default()).map_err(|e| e.error)
+}
+
+/// Вычисляет выражение, указывая в ошибке диапазон байтов, к которому она
+/// относится. Ошибки разбора относятся ко всему выражению.
+/// Пример: evaluate_spanned("1 / 0") → Err(DivideByZero @ 2..3)
+pub fn evaluate_spanned(input: &str) -> Result<f64, SpannedError> {
+    run(input, None, &[], None, &EvalOptions::default())
+}
+
+/// Проверяет синтаксис выражения, не вычисляя его: ошибки вычисления
+/// вроде деления на ноль не возникают.
+/// Пример: validate("1 / 0") → Ok(()), validate("1 /") → Err(InvalidExpression)
+pub fn validate(input: &str) -> Result<(), ParseError> {
+    ast::check_operands(parse(input)?)
+}
+
+/// Разобранное выражение, которое можно вычислять многократно без
+/// повторного разбора строки.
+/// Пример: CompiledExpr::compile("2 * 3")?.eval() → Ok(6.0)
+#[derive(Debug)]
+pub struct CompiledExpr {
+    rpn: VecDeque<Token>,
+}
+
+impl CompiledExpr {
+    /// Разбирает выражение и строит его ОПЗ.
+    pub fn compile(input: &str) -> Result<Self, CalcError> {
+        let rpn = rpn::to_rpn(parse(input)?)?;
+        Ok(CompiledExpr { rpn })
+    }
+
+    /// Вычисляет разобранное выражение.
+    pub fn eval(&self) -> Result<f64, CalcError> {
+        Ok(rpn::eval_rpn(&self.rpn)?)
+    }
+}
+
+/// Разбивает выражение на токены с позициями так же, как при вычислении
+/// с настройками `options`: с углами "90d30m" при `dms` и с закрытыми
+/// скобками при `lenient`.
+/// Пример: tokenize_with("(2+3", None, { lenient: true }) → ( 2 + 3 )
+pub fn tokenize_with(
+    input: &str,
+    vars: Option<&HashMap<String, f64>>,
+    options: &EvalOptions,
+) -> Result<Vec<Spanned>, ParseError> {
+    let mut lexer = match vars {
+        Some(vars) => Lexer::with_vars(input, vars),
+        None => Lexer::new(input),
+    }
+    .with_dms(options.dms);
+    let mut tokens = std::iter::from_fn(|| lexer.next_spanned()).collect::<Result<Vec<_>, _>>()?;
+    if options.lenient {
+        parser::close_parens(&mut tokens, input.len());
+    }
+    Ok(tokens)
+}
+
+/// Вычисляет выражение с настройками `options` и записывает состояние
+/// стека после каждого шага ОПЗ.
+/// Пример: explain("2 + 3", &EvalOptions::default()) → Ok((5.0, ["2 → [2]", "3 → [2, 3]", "+ → [5]"]))
+pub fn explain(input: &str, options: &EvalOptions) -> Result<(f64, Vec<String>), CalcError> {
+    let rpn = compile_in(input, None, &[], None, options, &mut RpnBuffers::default())
+        .map_err(|e| e.error)?;
+    let rpn: VecDeque<Token> = rpn.into_iter().map(|t| t.token).collect();
+    Ok(rpn::eval_rpn_traced(&rpn, options)?)
+}
+
+// Разбивает выражение на токены и проверяет скобки, вложенность и синтаксис
+fn parse(input: &str) -> Result<Vec<Token>, ParseError> {
+    let tokens = parser::tokenize_spanned(input, None)?;
+    parser::validate_parens(tokens.iter().map(|t| &t.token))?;
+    parser::validate_depth(tokens.iter().map(|t| &t.token), parser::MAX_NESTING_DEPTH)?;
+    parser::validate_syntax_spanned(&tokens)?;
+    Ok(tokens.into_iter().map(|t| t.token).collect())
+}
+
+// Полный цикл вычисления с подстановкой `ans`, ссылок "$N" на
+// результаты сеанса и переменных
+fn run(
+    input: &str,
+    ans: Option<f64>,
+    results: &[Option<f64>],
+    vars: Option<&HashMap<String, f64>>,
+    options: &EvalOptions,
+) -> Result<f64, SpannedError> {
+    run_in(
+        input,
+        ans,
+        results,
+        vars,
+        options,
+        &mut RpnBuffers::default(),
+    )
+}
+
+// То же, что `run`, но с переиспользуемыми буферами для ОПЗ
+fn run_in(
+    input: &str,
+    ans: Option<f64>,
+    results: &[Option<f64>],
+    vars: Option<&HashMap<String, f64>>,
+    options: &EvalOptions,
+    buffers: &mut RpnBuffers,
+) -> Result<f64, SpannedError> {
+    let rpn = compile_in(input, ans, results, vars, options, buffers)?;
+    rpn::eval_rpn_spanned(&rpn, options)
+}
+
+// Разбирает и проверяет выражение, подставляет значения и строит ОПЗ
+fn compile_in(
+    input: &str,
+    ans: Option<f64>,
+    results: &[Option<f64>],
+    vars: Option<&HashMap<String, f64>>,
+    options: &EvalOptions,
+    buffers: &mut RpnBuffers,
+) -> Result<VecDeque<Spanned>, SpannedError> {
+    let whole = |error: ParseError| SpannedError {
+        error: error.into(),
+        span: 0..input.len(),
+    };
+
+    let mut tokens = tokenize_with(input, vars, options).map_err(whole)?;
+    parser::validate_parens(tokens.iter().map(|t| &t.token)).map_err(|error| match error {
+        // Непарная скобка указывает на свой токен
+        ParseError::UnmatchedParensAt(i) => SpannedError {
+            error: error.into(),
+            span: tokens[i].span.clone(),
+        },
+        error => whole(error),
+    })?;
+    parser::validate_depth(tokens.iter().map(|t| &t.token), options.max_depth).map_err(whole)?;
+    parser::validate_syntax_spanned(&tokens).map_err(whole)?;
+
+    for spanned in tokens.iter_mut() {
+        let value = match &spanned.token {
+            Token::Ans => ans,
+            Token::HistoryRef(n) => results.get(n - 1).copied().flatten(),
+            Token::Variable(name) => vars.and_then(|vars| vars.get(name)).copied(),
+            _ => None,
+        };
+        if let Some(value) = value {
+            spanned.token = Token::Number(value);
+        }
+    }
+
+    rpn::to_rpn_spanned_in(tokens, buffers).map_err(whole)
+}
+
+/// Состояние интерактивного сеанса: переменные, результат предыдущего
+/// вычисления и единицы измерения углов.
+#[derive(Debug, Default)]
+pub struct Session {
+    variables: HashMap<String, f64>,
+    ans: Option<f64>,
+    // Результаты всех обработанных строк по порядку, None — ошибка.
+    // "$1" — результат первой строки, как в нумерации "history"
+    results: Vec<Option<f64>>,
+    options: EvalOptions,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Сеанс с заданными настройками вычисления.
+    pub fn with_options(options: EvalOptions) -> Self {
+        Session {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Возвращает значение переменной.
+    pub fn variable(&self, name: &str) -> Option<f64> {
+        self.variables.get(name).copied()
+    }
+
+    /// Возвращает результат последнего успешного вычисления.
+    pub fn ans(&self) -> Option<f64> {
+        self.ans
+    }
+
+    /// Возвращает результаты всех обработанных строк по порядку, None для
+    /// строк с ошибкой: на них ссылаются "$1", "$2" и т. д.
+    pub fn results(&self) -> &[Option<f64>] {
+        &self.results
+    }
+
+    /// Добавляет результат строки, вычисленной вне сеанса, чтобы номера
+    /// "$N" совпадали с историей: так учитываются строки из файла истории.
+    pub fn push_result(&mut self, result: Option<f64>) {
+        self.results.push(result);
+    }
+
+    /// Возвращает текущие единицы измерения углов.
+    pub fn angle_mode(&self) -> AngleMode {
+        self.options.angle_mode
+    }
+
+    /// Переключает единицы измерения углов для тригонометрических функций.
+    pub fn set_angle_mode(&mut self, mode: AngleMode) {
+        self.options.angle_mode = mode;
+    }
+
+    /// Включает процент от левого операнда: "100 + 20%" → 120.
+    pub fn set_percent_of(&mut self, enabled: bool) {
+        self.options.percent_of = enabled;
+    }
+
+    /// Закрывает незакрытые скобки в конце выражения: "(2 + 3" → 5.
+    pub fn set_lenient(&mut self, enabled: bool) {
+        self.options.lenient = enabled;
+    }
+
+    /// Включает углы в градусах, минутах и секундах: "90d30m" → 90.5.
+    pub fn set_dms(&mut self, enabled: bool) {
+        self.options.dms = enabled;
+    }
+
+    /// Обрабатывает строку ввода: присваивание `name = expr` или выражение.
+    /// Результат, в том числе ошибка, получает следующий номер "$N".
+    /// Пример: eval("x = 5") → Ok(5.0), затем eval("x * 2") → Ok(10.0)
+    pub fn eval(&mut self, input: &str) -> Result<f64, CalcError> {
+        let result = self.eval_line(input);
+        self.results.push(result.as_ref().ok().copied());
+        result
+    }
+
+    // Вычисляет строку и при успехе обновляет ans
+    fn eval_line(&mut self, input: &str) -> Result<f64, CalcError> {
+        let value = match split_assignment(input) {
+            Some((name, expr)) => {
+                let name = name.trim();
+                validate_var_name(name)?;
+                let value = run(
+                    expr,
+                    self.ans,
+                    &self.results,
+                    Some(&self.variables),
+                    &self.options,
+                )
+                .map_err(|e| e.error)?;
+                self.variables.insert(name.to_string(), value);
+                value
+            }
+            None => run(
+                input,
+                self.ans,
+                &self.results,
+                Some(&self.variables),
+                &self.options,
+            )
+            .map_err(|e| e.error)?,
+        };
+
+        self.ans = Some(value);
+        Ok(value)
+    }
+}
+
+// Делит строку по знаку присваивания "=", не считая им сравнения "==", "<=", ">=", "!="
+fn split_assignment(input: &str) -> Option<(&str, &str)> {
+    let bytes = input.as_bytes();
+    let index = (0..bytes.len()).find(|&i| {
+        bytes[i] == b'='
+            && !(i > 0 && matches!(bytes[i - 1], b'<' | b'>' | b'!' | b'='))
+            && bytes.get(i + 1) != Some(&b'=')
+    })?;
+    Some((&input[..index], &input[index + 1..]))
+}
+
+// Проверяет, что имя подходит для переменной и не зарезервировано
+fn validate_var_name(name: &str) -> Result<(), CalcError> {
+    let mut chars = name.chars();
+    let is_ident = chars.next().is_some_and(|c| c.is_alphabetic())
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+    if !is_ident {
+        return Err(ParseError::InvalidExpression(format!(
+            "Некорректное имя переменной: '{}'",
+            name
+        ))
+        .into());
+    }
+
+    if parser::is_reserved(name) {
+        return Err(
+            ParseError::InvalidExpression(format!("Имя '{}' зарезервировано", name)).into(),
+        );
+    }
+
+    Ok(())
+}
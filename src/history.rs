@@ -0,0 +1,182 @@
+// Сохранение истории диалога между запусками
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::CalcError;
+
+// Имя файла истории в домашнем каталоге
+pub const HISTORY_FILE: &str = ".rust_calc_history";
+
+// Переменная окружения, задающая путь к файлу истории вместо стандартного
+pub const HISTORY_ENV: &str = "RUST_CALC_HISTORY";
+
+// Сколько последних записей загружается при запуске
+pub const MAX_LOADED: usize = 1000;
+
+// Результат записан после табуляции: "2 + 3\t5", ошибка — после "\t!"
+const RESULT_SEPARATOR: char = '\t';
+const ERROR_MARK: char = '!';
+
+/// Запись истории: выражение и его сохранённый результат или текст ошибки.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub input: String,
+    pub result: Result<f64, String>,
+}
+
+impl Entry {
+    // Разбирает строку файла. В записях старого формата результата нет
+    fn parse(line: &str) -> Entry {
+        let Some((input, result)) = line.rsplit_once(RESULT_SEPARATOR) else {
+            return Entry {
+                input: line.to_string(),
+                result: Err("результат не сохранён".to_string()),
+            };
+        };
+        let result = match result.strip_prefix(ERROR_MARK) {
+            Some(message) => Err(message.to_string()),
+            None => result
+                .parse::<f64>()
+                .map_err(|_| format!("некорректный результат '{}'", result)),
+        };
+        Entry {
+            input: input.trim().to_string(),
+            result,
+        }
+    }
+}
+
+/// Выбирает путь к файлу истории: сначала `RUST_CALC_HISTORY`, затем
+/// `~/.rust_calc_history`. Без домашнего каталога история не сохраняется.
+pub fn history_path(env: Option<String>, home: Option<String>) -> Option<PathBuf> {
+    match env.filter(|p| !p.is_empty()) {
+        Some(path) => Some(PathBuf::from(path)),
+        None => home
+            .filter(|h| !h.is_empty())
+            .map(|h| Path::new(&h).join(HISTORY_FILE)),
+    }
+}
+
+// Путь к файлу истории по переменным окружения процесса
+pub fn default_history_path() -> Option<PathBuf> {
+    let home = std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .ok();
+    history_path(std::env::var(HISTORY_ENV).ok(), home)
+}
+
+/// Читает не больше `MAX_LOADED` последних записей вместе с результатами.
+/// Выражения заново не вычисляются. Отсутствующий файл считается пустой историей.
+pub fn load(path: &Path) -> io::Result<Vec<Entry>> {
+    match fs::read_to_string(path) {
+        Ok(content) => {
+            let lines: Vec<&str> = content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .collect();
+            let skip = lines.len().saturating_sub(MAX_LOADED);
+            Ok(lines[skip..]
+                .iter()
+                .map(|line| Entry::parse(line))
+                .collect())
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+// Дописывает выражение и его результат в конец файла истории, создавая
+// файл при необходимости
+pub fn append(path: &Path, line: &str, result: &Result<f64, CalcError>) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    match result {
+        Ok(value) => writeln!(file, "{}{}{}", line.trim(), RESULT_SEPARATOR, value),
+        Err(e) => writeln!(
+            file,
+            "{}{}{}{}",
+            line.trim(),
+            RESULT_SEPARATOR,
+            ERROR_MARK,
+            e
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, HISTORY_FILE, MAX_LOADED, append, history_path, load};
+    use crate::error::{CalcError, EvalError};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_history_path() {
+        let s = |v: &str| Some(v.to_string());
+        assert_eq!(
+            history_path(s("/tmp/calc_history"), s("/home/user")),
+            Some(PathBuf::from("/tmp/calc_history"))
+        );
+        assert_eq!(
+            history_path(None, s("/home/user")),
+            Some(PathBuf::from("/home/user").join(HISTORY_FILE))
+        );
+        assert_eq!(
+            history_path(s(""), s("/home/user")),
+            Some(PathBuf::from("/home/user").join(HISTORY_FILE))
+        );
+        assert_eq!(history_path(None, None), None);
+    }
+
+    #[test]
+    fn test_append_load_round_trip() {
+        let path = std::env::temp_dir().join(format!("calculator_history_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        assert!(load(&path).unwrap().is_empty());
+        append(&path, "2 + 3", &Ok(5.0)).unwrap();
+        append(&path, "  ans * 0.1 ", &Ok(0.5)).unwrap();
+        append(
+            &path,
+            "1 / 0",
+            &Err(CalcError::Eval(EvalError::DivideByZero)),
+        )
+        .unwrap();
+        let entries = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let entry = |input: &str, result| Entry {
+            input: input.to_string(),
+            result,
+        };
+        assert_eq!(
+            entries,
+            vec![
+                entry("2 + 3", Ok(5.0)),
+                entry("ans * 0.1", Ok(0.5)),
+                entry(
+                    "1 / 0",
+                    Err(CalcError::Eval(EvalError::DivideByZero).to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_old_format_and_limit() {
+        let path =
+            std::env::temp_dir().join(format!("calculator_history_old_{}", std::process::id()));
+        // Записи без результата остаются в истории, но не вычисляются заново
+        let content: String = (0..MAX_LOADED + 5)
+            .map(|i| format!("rand() + {}\n", i))
+            .collect();
+        std::fs::write(&path, content).unwrap();
+        let entries = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(entries.len(), MAX_LOADED);
+        assert_eq!(entries[0].input, "rand() + 5");
+        assert!(entries[0].result.is_err());
+    }
+}
@@ -1,4 +1,15 @@
+pub mod ast;
 pub mod error;
+pub mod eval;
+pub mod history;
 pub mod output;
 pub mod parser;
+pub mod random;
 pub mod rpn;
+pub mod testing;
+
+pub use eval::{
+    CompiledExpr, EvalResult, Number, Session, evaluate, evaluate_detailed, evaluate_many,
+    evaluate_sequence, evaluate_sequence_with, evaluate_spanned, evaluate_with_ans,
+    evaluate_with_vars, explain, tokenize_with, validate,
+};
@@ -1,231 +1,422 @@
-use calculator::{error::CalcError, output, parser, rpn};
+use calculator::{
+    Session,
+    error::{CalcError, EvalError, ParseError},
+    evaluate_sequence_with, history,
+    output::{self, NumberFormat},
+    parser::Spanned,
+    random,
+    rpn::{AngleMode, EvalOptions, to_rpn_spanned},
+    tokenize_with,
+};
 use std::io;
+use std::path::Path;
+use std::process::ExitCode;
+
+// Ошибка запуска калькулятора; каждому виду соответствует свой код выхода
+#[derive(Debug)]
+enum AppError {
+    // Выражение не удалось вычислить
+    Eval(CalcError),
+    // Ошибка вычисления, о которой уже сообщено в выводе (--json, --batch)
+    Reported,
+    // Неверные аргументы командной строки
+    Usage(String),
+    // Ошибка чтения или записи
+    Io(String),
+}
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-
-    if output::is_not_interactive() {
-        // Режим CLI
-        // FIX: сделать обработку передачи выражения с пробелами или заключатъ выражение в ""
-        let input = args[1].trim();
-        match run_repl(input) {
-            Ok(num) => println!("{}", num),
-            Err(e) => {
-                eprintln!("Error: {}", e);
-                std::process::exit(1);
-            }
+impl AppError {
+    // 0 — успех, 1 — ошибка вычисления, 2 — ошибка аргументов, 64 — ошибка ввода-вывода
+    fn exit_code(&self) -> u8 {
+        match self {
+            AppError::Eval(_) | AppError::Reported => 1,
+            AppError::Usage(_) => 2,
+            AppError::Io(_) => 64,
         }
-        return;
     }
-
-    // Итерактивный режим
-    run_repl_interactive().unwrap();
 }
 
-fn run_repl_interactive() -> Result<(), CalcError> {
-    output::print_prompt();
-    loop {
-        let input = read_input();
-        if &input == "exit" {
-            break;
-        }
-
-        match run_repl(&input) {
-            Ok(num) => output::print_result(num),
-            Err(e) => output::print_error(&e.to_string()),
-        }
+impl From<CalcError> for AppError {
+    fn from(error: CalcError) -> Self {
+        AppError::Eval(error)
     }
+}
 
-    Ok(())
+impl From<ParseError> for AppError {
+    fn from(error: ParseError) -> Self {
+        AppError::Eval(error.into())
+    }
 }
 
-fn read_input() -> String {
-    loop {
-        let mut s = String::new();
-        if io::stdin().read_line(&mut s).is_err() {
-            eprintln!("Ошибка чтения ввода.");
-            continue;
-        }
-        return s.trim().to_owned();
+impl From<EvalError> for AppError {
+    fn from(error: EvalError) -> Self {
+        AppError::Eval(error.into())
     }
 }
 
-/// Обрабатывает выражение и возвращает результат
-fn run_repl(input: &str) -> Result<f64, CalcError> {
-    let tokens = parser::tokenize(input)?;
-    parser::validate_parens(&tokens)?;
-    let rpn = rpn::to_rpn(tokens)?;
-    rpn::eval_rpn(rpn)
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            match &error {
+                AppError::Eval(e) => eprintln!("Error: {}", e),
+                AppError::Usage(msg) | AppError::Io(msg) => eprintln!("Error: {}", msg),
+                AppError::Reported => {}
+            }
+            ExitCode::from(error.exit_code())
+        }
+    }
 }
 
-#[cfg(test)]
-mod tests_run_repl {
-    use super::*;
+fn run() -> Result<(), AppError> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
 
-    #[test]
-    fn test_simple_expression() {
-        assert_eq!(run_repl("2 + 3").unwrap(), 5.0);
-        assert_eq!(run_repl("10 - 4").unwrap(), 6.0);
-        assert_eq!(run_repl("3 * 4").unwrap(), 12.0);
-        assert_eq!(run_repl("8 / 2").unwrap(), 4.0);
+    // Всё после "--" и значение --eval EXPR считаются выражением, даже если
+    // похожи на флаг: `calculator -- -5 + 3`, `calculator --eval "-q"`
+    let mut expression = match args.iter().position(|arg| arg == "--") {
+        Some(index) => {
+            let rest = args.split_off(index + 1);
+            args.pop();
+            rest
+        }
+        None => Vec::new(),
+    };
+    if let Some(expr) = take_option(&mut args, "--eval") {
+        expression.insert(0, expr);
     }
 
-    #[test]
-    fn test_operator_precedence() {
-        assert_eq!(run_repl("2 + 3 * 4").unwrap(), 14.0); // 2 + (3*4)
-        assert_eq!(run_repl("10 - 2 * 3").unwrap(), 4.0); // 10 - (2*3)
-        assert_eq!(run_repl("1 + 2 * 3 - 4").unwrap(), 3.0); // 1 + (2*3) - 4
+    // Флаг --version: печатается версия, выражение не вычисляется
+    if take_flag(&mut args, "--version") {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        return Ok(());
     }
 
-    #[test]
-    fn test_parentheses() {
-        assert_eq!(run_repl("(2 + 3) * 4").unwrap(), 20.0); // (2+3)*4
-        assert_eq!(run_repl("((2 + 3) * 4) - 5").unwrap(), 15.0); // ((2+3)*4)-5
-        assert_eq!(run_repl("2 * (3 + (4 * 5))").unwrap(), 46.0); // 2*(3+(4*5))
+    // Флаг --json: результат и ошибки печатаются в stdout в формате JSON
+    let json = take_flag(&mut args, "--json");
+    // Флаг --no-color: вывод без ANSI-цветов
+    if take_flag(&mut args, "--no-color") {
+        output::set_no_color(true);
+    }
+    // Флаг --quiet (-q): в диалоге печатается только число, без подписи и цвета
+    let quiet = take_flag(&mut args, "--quiet") | take_flag(&mut args, "-q");
+    // Настройки вычисления для выражения из аргументов, диалога и --batch/--file
+    let options = EvalOptions {
+        // Флаг --percent-of: "100 + 20%" равно 120
+        percent_of: take_flag(&mut args, "--percent-of"),
+        // Флаг --dms: углы в градусах, минутах и секундах, "90d30m" равно 90.5
+        dms: take_flag(&mut args, "--dms"),
+        // Флаг --lenient: незакрытые скобки закрываются в конце, "(2 + 3" равно 5
+        lenient: take_flag(&mut args, "--lenient"),
+        ..EvalOptions::default()
+    };
+    // Флаг --precision N: число знаков после запятой в результате
+    let precision = match take_option(&mut args, "--precision").map(|n| n.parse::<usize>()) {
+        Some(Ok(n)) => Some(n),
+        Some(Err(_)) => {
+            return Err(AppError::Usage(
+                "--precision ожидает неотрицательное целое число".to_string(),
+            ));
+        }
+        None => None,
+    };
+
+    // Флаг --format dec|hex|bin: система счисления для целых результатов
+    let format = match take_option(&mut args, "--format") {
+        Some(name) => NumberFormat::from_name(&name).ok_or_else(|| {
+            AppError::Usage("--format ожидает одно из значений: dec, hex, bin".to_string())
+        })?,
+        None => NumberFormat::Decimal,
+    };
+
+    // Флаг --seed N: начальное значение генератора для rand()
+    match take_option(&mut args, "--seed").map(|n| n.parse::<u64>()) {
+        Some(Ok(seed)) => random::set_seed(seed),
+        Some(Err(_)) => {
+            return Err(AppError::Usage(
+                "--seed ожидает неотрицательное целое число".to_string(),
+            ));
+        }
+        None => {}
     }
 
-    #[test]
-    fn test_unary_minus() {
-        assert_eq!(run_repl("-5").unwrap(), -5.0);
-        assert_eq!(run_repl("2 + (-3)").unwrap(), -1.0);
-        assert_eq!(run_repl("-(-4)").unwrap(), 4.0);
-        assert_eq!(run_repl("2^(-1)").unwrap(), 0.5);
+    // Флаг --prompt TEXT: приглашение диалога. Без флага берётся PS_CALC
+    let prompt = output::resolve_prompt(
+        take_option(&mut args, "--prompt"),
+        std::env::var("PS_CALC").ok(),
+    );
+
+    // Флаг --no-history: история диалога не загружается и не сохраняется
+    let history_path = if take_flag(&mut args, "--no-history") {
+        None
+    } else {
+        history::default_history_path()
+    };
+
+    // Флаг --file PATH: выражения читаются из файла построчно, как в --batch
+    let file = take_option(&mut args, "--file");
+    // Флаг --batch: выражения читаются из stdin построчно
+    let batch = take_flag(&mut args, "--batch");
+    // Флаг --tokens: выражение не вычисляется, печатается список токенов
+    let tokens_only = take_flag(&mut args, "--tokens");
+    // Флаг --rpn: печатается выражение в обратной польской записи
+    let rpn_only = take_flag(&mut args, "--rpn");
+    // Флаг --explain: печатается стек после каждого шага вычисления ОПЗ
+    let explain = take_flag(&mut args, "--explain");
+
+    // Оставшиеся "--имя" — неизвестные флаги, а не часть выражения
+    if let Some(flag) = args.iter().find(|arg| is_flag(arg)) {
+        return Err(AppError::Usage(format!("неизвестный флаг '{}'", flag)));
+    }
+    args.extend(expression);
+
+    if let Some(path) = file {
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| AppError::Io(format!("не удалось прочитать файл '{}': {}", path, e)))?;
+        return run_batch(
+            content.lines().map(|line| Ok(line.to_string())),
+            json,
+            precision,
+            format,
+            options,
+        );
     }
 
-    #[test]
-    fn test_power_right_associativity() {
-        assert_eq!(run_repl("2^3^2").unwrap(), 512.0); // 2^(3^2)
-        assert_eq!(run_repl("2^(3^2)").unwrap(), 512.0);
-        assert_eq!(run_repl("(2^3)^2").unwrap(), 64.0);
-        assert_eq!(run_repl("3^2^2").unwrap(), 81.0); // 3^(2^2) = 3^4
+    if batch {
+        return run_batch(io::stdin().lines(), json, precision, format, options);
     }
 
-    #[test]
-    fn test_divide_by_zero() {
-        let err = run_repl("1 / 0").unwrap_err();
-        assert_eq!(err.to_string(), "Деление на 0.");
+    if tokens_only {
+        let input = args.join(" ");
+        let tokens = tokenize_with(input.trim(), None, &options)?;
+        println!("{}", join_tokens(input.trim(), &tokens));
+        return Ok(());
     }
 
-    #[test]
-    fn test_invalid_tokens() {
-        let err = run_repl("2 + abc").unwrap_err();
-        assert!(err.to_string().contains("Некорректный символ"));
+    if rpn_only {
+        let input = args.join(" ");
+        let rpn = tokenize_with(input.trim(), None, &options).and_then(to_rpn_spanned)?;
+        println!("{}", join_tokens(input.trim(), &rpn));
+        return Ok(());
+    }
 
-        let err = run_repl("1.2.3").unwrap_err();
-        assert!(err.to_string().contains("Некорректный символ"));
+    if explain {
+        let input = args.join(" ");
+        match calculator::explain(input.trim(), &options) {
+            Ok((result, steps)) if json => println!("{}", output::json_explain(result, &steps)),
+            Ok((result, steps)) => {
+                for step in steps {
+                    println!("{}", step);
+                }
+                println!("= {}", output::format_in(result, precision, format));
+            }
+            Err(e) if json => {
+                println!("{}", output::json_error(&e));
+                return Err(AppError::Reported);
+            }
+            Err(e) => return Err(AppError::Eval(e)),
+        }
+        return Ok(());
+    }
 
-        let err = run_repl("1 + 2 *").unwrap_err();
-        assert!(err.to_string().contains("Некорректное выражение"));
+    // Флаги не считаются выражением: `calculator --no-color` запускает диалог
+    if !args.is_empty() {
+        // Режим CLI: аргументы склеиваются, так что `2 + 3` и "2 + 3" равнозначны.
+        // Выражения через ";" печатаются каждое на своей строке.
+        // Числа через пробел не склеиваются: "1 000" — ошибка, разряды пишутся через "_".
+        let input = args.join(" ");
+        match evaluate_sequence_with(input.trim(), &options) {
+            Ok(results) => {
+                for num in results {
+                    if json {
+                        println!("{}", output::json_result(num));
+                    } else {
+                        println!("{}", output::format_in(num, precision, format));
+                    }
+                }
+            }
+            Err(e) if json => {
+                println!("{}", output::json_error(&e));
+                return Err(AppError::Reported);
+            }
+            Err(e) => return Err(AppError::Eval(e)),
+        }
+        return Ok(());
     }
 
-    #[test]
-    fn test_unmatched_parens() {
-        let err = run_repl("(2 + 3").unwrap_err();
-        assert_eq!(err.to_string(), "Не совпадают скобки.");
+    // Итерактивный режим
+    run_repl_interactive(
+        precision,
+        format,
+        quiet,
+        options,
+        &prompt,
+        history_path.as_deref(),
+    )
+}
+
+// Склеивает токены через пробел; числа берутся в записи из входной строки
+fn join_tokens<'a>(input: &str, tokens: impl IntoIterator<Item = &'a Spanned>) -> String {
+    tokens
+        .into_iter()
+        .map(|token| token.lexeme(input))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-        let err = run_repl("2 + 3)").unwrap_err();
-        assert_eq!(err.to_string(), "Не совпадают скобки.");
+// Аргумент вида "--имя" считается флагом; "--5" остаётся частью выражения
+fn is_flag(arg: &str) -> bool {
+    arg.strip_prefix("--")
+        .and_then(|name| name.chars().next())
+        .is_some_and(|c| c.is_alphabetic())
+}
 
-        let err = run_repl("((2 + 3) * 4").unwrap_err();
-        assert_eq!(err.to_string(), "Не совпадают скобки.");
+// Удаляет флаг из списка аргументов и сообщает, был ли он указан
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let len = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != len
+}
+
+// Удаляет параметр со значением из списка аргументов и возвращает значение.
+// Без значения параметр считается пустым.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    args.remove(index);
+    if index < args.len() {
+        Some(args.remove(index))
+    } else {
+        Some(String::new())
     }
+}
+
+// Вычисляет каждую строку и печатает по одному результату или ошибке
+// на строку, не останавливаясь на ошибках. Пустые строки и комментарии,
+// начинающиеся с "#", пропускаются.
+// Возвращает AppError::Reported, если хотя бы одно выражение не удалось вычислить.
+fn run_batch(
+    lines: impl Iterator<Item = io::Result<String>>,
+    json: bool,
+    precision: Option<usize>,
+    format: NumberFormat,
+    options: EvalOptions,
+) -> Result<(), AppError> {
+    let mut session = Session::with_options(options);
+    let mut all_ok = true;
+
+    for line in lines {
+        let line = line.map_err(|e| AppError::Io(format!("ошибка чтения ввода: {}", e)))?;
+        let input = line.trim();
+        if input.is_empty() || input.starts_with('#') {
+            continue;
+        }
 
-    #[test]
-    fn test_mixed_operations() {
-        assert_eq!(run_repl("2 + 3 * (4 - 1)^2").unwrap(), 29.0); // 2 + 3*(3^2)
-        assert_eq!(run_repl("10 / (2 + 3) * 4").unwrap(), 8.0); // (10/5)*4
-        assert_eq!(run_repl("-2^3").unwrap(), -8.0); // -(2^3)
-        assert_eq!(run_repl("(-2)^3").unwrap(), -8.0); // (-2)^3
+        match session.eval(input) {
+            Ok(num) if json => println!("{}", output::json_result(num)),
+            Ok(num) => println!("{}", output::format_in(num, precision, format)),
+            Err(e) => {
+                all_ok = false;
+                if json {
+                    println!("{}", output::json_error(&e));
+                } else {
+                    println!("Error: {}", e);
+                }
+            }
+        }
     }
 
-    #[test]
-    fn test_large_numbers() {
-        assert_eq!(run_repl("1234567890 + 987654321").unwrap(), 2222222211.0);
-        // assert_eq!(run_repl("1e6 * 1e3").unwrap(), 1_000_000_000.0); // 10^6 * 10^3
-        assert_eq!(run_repl("2^10").unwrap(), 1024.0);
+    if all_ok {
+        Ok(())
+    } else {
+        Err(AppError::Reported)
     }
+}
 
-    #[test]
-    fn test_edge_cases() {
-        // Пустое выражение
-        let err = run_repl("").unwrap_err();
-        assert!(matches!(err, CalcError::InvalidExpression(_)));
-        assert_eq!(
-            err.to_string(),
-            "Некорректное выражение: Стек пуст после вычислений"
-        );
+fn run_repl_interactive(
+    mut precision: Option<usize>,
+    format: NumberFormat,
+    quiet: bool,
+    options: EvalOptions,
+    prompt: &str,
+    history_path: Option<&Path>,
+) -> Result<(), AppError> {
+    let mut history: Vec<(String, Result<f64, String>)> = Vec::new();
+    let mut session = Session::with_options(options);
+
+    // Сохранённые записи не вычисляются заново: результаты берутся из файла,
+    // так что rand() не сдвигает генератор, заданный --seed.
+    // Их результаты занимают первые номера "$N", как и в "history"
+    if let Some(path) = history_path {
+        match history::load(path) {
+            Ok(entries) => {
+                for entry in entries {
+                    session.push_result(entry.result.as_ref().ok().copied());
+                    history.push((entry.input, entry.result));
+                }
+            }
+            Err(e) => output::print_error(&format!("не удалось прочитать историю: {}", e)),
+        }
+    }
 
-        // Незакрытая скобка
-        let err = run_repl(")").unwrap_err();
-        assert!(matches!(err, CalcError::UnmatchedParens));
-        assert_eq!(err.to_string(), "Не совпадают скобки.");
-
-        // Некорректный оператор
-        let err = run_repl("1 + 2 * / 3").unwrap_err();
-        assert!(matches!(err, CalcError::InvalidExpression(_)));
-        assert_eq!(
-            err.to_string(),
-            "Некорректное выражение: Недостаточно операндов для операции 'Plus'"
-        );
+    output::print_prompt(prompt);
+    // Конец ввода (Ctrl+D) завершает диалог так же, как "exit"
+    while let Some(input) = read_input()? {
+        if &input == "exit" {
+            break;
+        }
 
-        // Деление на ноль
-        let err = run_repl("1 / 0").unwrap_err();
-        assert!(matches!(err, CalcError::DivideByZero));
-        assert_eq!(err.to_string(), "Деление на 0.");
-
-        // Недостаточно операндов для операции
-        let err = run_repl("1 +").unwrap_err();
-        assert!(matches!(err, CalcError::InvalidExpression(_)));
-        assert_eq!(
-            err.to_string(),
-            "Некорректное выражение: Недостаточно операндов для операции 'Plus'"
-        );
+        if &input == "history" {
+            for (i, (expr, result)) in history.iter().enumerate() {
+                output::print_history_entry(i + 1, expr, result);
+            }
+            continue;
+        }
 
-        // Унарная операция без операнда
-        let err = run_repl("-").unwrap_err();
-        assert!(matches!(err, CalcError::InvalidExpression(_)));
-        assert_eq!(
-            err.to_string(),
-            "Некорректное выражение: Унарный минус требует одного операнда"
-        );
+        match input.as_str() {
+            "clear" => {
+                output::clear_screen();
+                continue;
+            }
+            "mode degrees" => {
+                session.set_angle_mode(AngleMode::Degrees);
+                continue;
+            }
+            "mode radians" => {
+                session.set_angle_mode(AngleMode::Radians);
+                continue;
+            }
+            _ => {}
+        }
 
-        // Лишние числа в стеке
-        let err = run_repl("1 2 + 2").unwrap_err();
-        assert!(matches!(err, CalcError::InvalidExpression(_)));
-        assert_eq!(
-            err.to_string(),
-            "Некорректное выражение: В стеке остались лишние числа"
-        );
+        if let Some(value) = input.strip_prefix("precision ") {
+            match value.trim().parse::<usize>() {
+                Ok(n) => precision = Some(n),
+                Err(_) => output::print_error("precision ожидает неотрицательное целое число"),
+            }
+            continue;
+        }
 
-        // Некорректный токен
-        let err = run_repl("abc").unwrap_err();
-        assert!(matches!(err, CalcError::InvalidToken(_)));
-        assert_eq!(
-            err.to_string(),
-            "Некорректный символ: Некорректный символ в выражении: 'a'"
-        );
+        let result = session.eval(&input);
+        if let Some(path) = history_path
+            && let Err(e) = history::append(path, &input, &result)
+        {
+            output::print_error(&format!("не удалось сохранить историю: {}", e));
+        }
+        match &result {
+            Ok(num) => output::print_result(*num, precision, format, quiet),
+            Err(e) => output::print_error(&e.to_string()),
+        }
+        history.push((input, result.map_err(|e| e.to_string())));
+    }
 
-        // Несколько точек в числе
-        let err = run_repl("1.2.3").unwrap_err();
-        assert!(matches!(err, CalcError::InvalidToken(_)));
-        assert_eq!(err.to_string(), "Некорректный символ: 1.2.3");
-
-        // Незакрытые скобки в начале выражения
-        let err = run_repl("((2 + 3)").unwrap_err();
-        assert!(matches!(err, CalcError::UnmatchedParens));
-        assert_eq!(err.to_string(), "Не совпадают скобки.");
-
-        // Лишние закрывающие скобки
-        let err = run_repl("2 + 3))").unwrap_err();
-        assert!(matches!(err, CalcError::UnmatchedParens));
-        assert_eq!(err.to_string(), "Не совпадают скобки.");
-
-        // Оператор в конце выражения без операндов
-        let err = run_repl("5 + 2 *").unwrap_err();
-        assert!(matches!(err, CalcError::InvalidExpression(_)));
-        assert_eq!(
-            err.to_string(),
-            "Некорректное выражение: Недостаточно операндов для операции 'Plus'"
-        );
+    Ok(())
+}
+
+// Читает строку ввода; None означает конец ввода
+fn read_input() -> Result<Option<String>, AppError> {
+    let mut s = String::new();
+    match io::stdin().read_line(&mut s) {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(s.trim().to_owned())),
+        Err(e) => Err(AppError::Io(format!("ошибка чтения ввода: {}", e))),
     }
 }
@@ -1,45 +1,501 @@
+use std::io::Write;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::CalcError;
+
 // ANSI-коды для цветов
 pub const RED: &str = "\x1b[31m";
 pub const GREEN: &str = "\x1b[32m";
 pub const YELLOW: &str = "\x1b[33m";
 pub const RESET: &str = "\x1b[0m";
+// Цвета по именам для переменных CALC_COLOR_*
+const COLOR_NAMES: &[(&str, &str)] = &[
+    ("black", "\x1b[30m"),
+    ("red", RED),
+    ("green", GREEN),
+    ("yellow", YELLOW),
+    ("blue", "\x1b[34m"),
+    ("magenta", "\x1b[35m"),
+    ("cyan", "\x1b[36m"),
+    ("white", "\x1b[37m"),
+];
+
+// Переменные окружения, переопределяющие цвета результата, ошибок и приглашения
+pub const COLOR_RESULT_ENV: &str = "CALC_COLOR_RESULT";
+pub const COLOR_ERROR_ENV: &str = "CALC_COLOR_ERROR";
+pub const COLOR_PROMPT_ENV: &str = "CALC_COLOR_PROMPT";
+
+/// Возвращает ANSI-код цвета по имени без учёта регистра: "blue" → "\x1b[34m".
+pub fn color_code(name: &str) -> Option<&'static str> {
+    let name = name.trim().to_lowercase();
+    COLOR_NAMES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|&(_, code)| code)
+}
+
+/// Цвета вывода: результат, ошибки и приглашение.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorTheme {
+    pub result: &'static str,
+    pub error: &'static str,
+    pub prompt: &'static str,
+}
+
+impl Default for ColorTheme {
+    fn default() -> Self {
+        ColorTheme {
+            result: GREEN,
+            error: RED,
+            prompt: YELLOW,
+        }
+    }
+}
+
+impl ColorTheme {
+    /// Тема с цветами, заданными по имени. Отсутствующее или неизвестное
+    /// имя оставляет цвет по умолчанию.
+    /// Пример: from_names(Some("blue"), None, Some("pink")) → синий результат,
+    /// остальные цвета стандартные
+    pub fn from_names(result: Option<&str>, error: Option<&str>, prompt: Option<&str>) -> Self {
+        let default = ColorTheme::default();
+        let pick = |name: Option<&str>, fallback| name.and_then(color_code).unwrap_or(fallback);
+        ColorTheme {
+            result: pick(result, default.result),
+            error: pick(error, default.error),
+            prompt: pick(prompt, default.prompt),
+        }
+    }
+
+    // Тема по переменным окружения CALC_COLOR_RESULT, CALC_COLOR_ERROR, CALC_COLOR_PROMPT
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok();
+        ColorTheme::from_names(
+            var(COLOR_RESULT_ENV).as_deref(),
+            var(COLOR_ERROR_ENV).as_deref(),
+            var(COLOR_PROMPT_ENV).as_deref(),
+        )
+    }
+}
 
-// Проверяет, запущен ли в интерактивном режиме
-pub fn is_not_interactive() -> bool {
-    std::env::args().len() > 1
+// Тема вывода читается из окружения один раз при первом использовании
+pub fn theme() -> &'static ColorTheme {
+    static THEME: OnceLock<ColorTheme> = OnceLock::new();
+    THEME.get_or_init(ColorTheme::from_env)
+}
+
+// Очистка экрана и перевод курсора в левый верхний угол
+pub const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+// Цвета отключены флагом --no-color
+static NO_COLOR_FLAG: AtomicBool = AtomicBool::new(false);
+
+// Отключает или включает ANSI-цвета независимо от окружения
+pub fn set_no_color(disabled: bool) {
+    NO_COLOR_FLAG.store(disabled, Ordering::Relaxed);
 }
 
 // Проверяет, поддерживает ли терминал ANSI-цвета
 pub fn supports_ansi() -> bool {
-    !cfg!(windows) || std::env::var("TERM").is_ok()
+    // По соглашению NO_COLOR учитывается, только если переменная не пуста
+    let no_color = std::env::var("NO_COLOR").is_ok_and(|v| !v.is_empty());
+    use_ansi(
+        cfg!(windows),
+        std::env::var("TERM").is_ok(),
+        no_color || NO_COLOR_FLAG.load(Ordering::Relaxed),
+    )
+}
+
+// Решает, выводить ли ANSI-цвета: не при явном запрете и не в Windows без TERM
+pub fn use_ansi(windows: bool, term_set: bool, no_color: bool) -> bool {
+    !no_color && (!windows || term_set)
+}
+
+// Число значащих цифр для дробных результатов
+const SIGNIFICANT_DIGITS: i32 = 10;
+
+// Форматирует число: целые без дробной части, дробные — не более
+// 10 значащих цифр без хвостовых нулей.
+// Пример: 5.0 → "5", 0.1 + 0.2 → "0.3"
+pub fn format_number(num: f64) -> String {
+    if !num.is_finite() {
+        return num.to_string();
+    }
+
+    if num.fract() == 0.0 {
+        // Избавляемся от "-0"
+        return format!("{}", num + 0.0);
+    }
+
+    let magnitude = num.abs().log10().floor() as i32;
+    let decimals = (SIGNIFICANT_DIGITS - 1 - magnitude).max(0) as usize;
+    let formatted = format!("{:.*}", decimals, num);
+    if formatted.contains('.') {
+        formatted
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        formatted
+    }
+}
+
+// Форматирует число с заданным числом знаков после запятой,
+// а без него — как format_number.
+// Пример: (2.0 / 3.0, Some(2)) → "0.67"
+pub fn format_with_precision(num: f64, precision: Option<usize>) -> String {
+    match precision {
+        Some(n) if num.is_finite() => format!("{:.*}", n, num),
+        _ => format_number(num),
+    }
+}
+
+// Система счисления для вывода результата
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NumberFormat {
+    #[default]
+    Decimal,
+    Hex,
+    Binary,
+}
+
+impl NumberFormat {
+    /// Разбирает значение флага --format: "dec", "hex" или "bin".
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "dec" => Some(NumberFormat::Decimal),
+            "hex" => Some(NumberFormat::Hex),
+            "bin" => Some(NumberFormat::Binary),
+            _ => None,
+        }
+    }
+}
+
+// Форматирует число в выбранной системе счисления. Шестнадцатеричная и
+// двоичная запись возможны только для целых чисел; остальные выводятся
+// в десятичной записи с пометкой.
+// Пример: (255.0, Hex) → "0xff", (-5.0, Binary) → "-0b101"
+pub fn format_in(num: f64, precision: Option<usize>, format: NumberFormat) -> String {
+    if format == NumberFormat::Decimal {
+        return format_with_precision(num, precision);
+    }
+
+    // Целые за пределами i64 теряют точность, поэтому тоже остаются десятичными
+    if num.fract() != 0.0 || !num.is_finite() || num.abs() >= 2f64.powi(63) {
+        return format!(
+            "{} (не целое число, показано в десятичной записи)",
+            format_with_precision(num, precision)
+        );
+    }
+
+    let sign = if num < 0.0 { "-" } else { "" };
+    let magnitude = (num as i64).unsigned_abs();
+    match format {
+        NumberFormat::Hex => format!("{}0x{:x}", sign, magnitude),
+        _ => format!("{}0b{:b}", sign, magnitude),
+    }
+}
+
+// Экранирует строку для JSON
+fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Результат в формате JSON: {"ok":true,"result":5.0}
+pub fn json_result(result: f64) -> String {
+    if result.is_finite() {
+        format!("{{\"ok\":true,\"result\":{:?}}}", result)
+    } else {
+        "{\"ok\":true,\"result\":null}".to_string()
+    }
+}
+
+// Результат с шагами вычисления в формате JSON:
+// {"ok":true,"result":5.0,"steps":["2 → [2]","3 → [2, 3]","+ → [5]"]}
+pub fn json_explain(result: f64, steps: &[String]) -> String {
+    let result = json_result(result);
+    let steps: Vec<String> = steps
+        .iter()
+        .map(|step| format!("\"{}\"", json_escape(step)))
+        .collect();
+    format!(
+        "{},\"steps\":[{}]}}",
+        &result[..result.len() - 1],
+        steps.join(",")
+    )
+}
+
+// Ошибка в формате JSON: {"ok":false,"error":"Деление на 0."}
+pub fn json_error(error: &CalcError) -> String {
+    format!(
+        "{{\"ok\":false,\"error\":\"{}\"}}",
+        json_escape(&error.to_string())
+    )
 }
 
 // Форматированный вывод ошибок
 pub fn print_error(message: &str) {
     if supports_ansi() {
-        eprintln!("{}Error:{} {}", RED, RESET, message);
+        eprintln!("{}Error:{} {}", theme().error, RESET, message);
     } else {
         eprintln!("Error: {}", message)
     }
 }
 
-// Форматированный вывод результата
-pub fn print_result(result: f64) {
-    if supports_ansi() {
-        println!("{}Результат: {}{}", GREEN, result, RESET);
+// Строка результата: с подписью и цветом или только число
+pub fn format_result(
+    result: f64,
+    precision: Option<usize>,
+    format: NumberFormat,
+    decorated: bool,
+) -> String {
+    let formatted = format_in(result, precision, format);
+    if decorated {
+        format!("{}Результат: {}{}", theme().result, formatted, RESET)
     } else {
-        println!("{}", result)
+        formatted
+    }
+}
+
+// Форматированный вывод результата. В тихом режиме печатается только число
+pub fn print_result(result: f64, precision: Option<usize>, format: NumberFormat, quiet: bool) {
+    println!(
+        "{}",
+        format_result(result, precision, format, !quiet && supports_ansi())
+    );
+}
+
+// Форматированный вывод записи истории
+pub fn print_history_entry<E: std::fmt::Display>(
+    index: usize,
+    input: &str,
+    result: &Result<f64, E>,
+) {
+    match result {
+        Ok(num) if supports_ansi() => println!(
+            "{}: {} = {}{}{}",
+            index,
+            input,
+            theme().result,
+            format_number(*num),
+            RESET
+        ),
+        Ok(num) => println!("{}: {} = {}", index, input, format_number(*num)),
+        Err(e) if supports_ansi() => {
+            println!("{}: {} → {}{}{}", index, input, theme().error, e, RESET)
+        }
+        Err(e) => println!("{}: {} → {}", index, input, e),
     }
 }
 
+// Последовательность очистки экрана; без поддержки ANSI экран не очищается
+pub fn clear_sequence(ansi: bool) -> &'static str {
+    if ansi { CLEAR_SCREEN } else { "" }
+}
+
+// Очищает экран терминала
+pub fn clear_screen() {
+    print!("{}", clear_sequence(supports_ansi()));
+    let _ = std::io::stdout().flush();
+}
+
 // Форматированный вывод приглашения
-pub fn print_prompt() {
+pub fn print_prompt(prompt: &str) {
     if supports_ansi() {
-        println!(
-            "{}Введите выражение (или 'exit' для выхода):{}",
-            YELLOW, RESET
-        );
+        println!("{}{}{}", theme().prompt, prompt, RESET);
     } else {
-        println!("Введите выражение (или 'exit' для выхода):");
+        println!("{}", prompt);
+    }
+}
+
+// Приглашение по умолчанию
+pub const DEFAULT_PROMPT: &str = "Введите выражение (или 'exit' для выхода):";
+
+// Выбирает приглашение: флаг --prompt, затем переменная PS_CALC, затем стандартное
+pub fn resolve_prompt(flag: Option<String>, env: Option<String>) -> String {
+    flag.or(env).unwrap_or_else(|| DEFAULT_PROMPT.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CLEAR_SCREEN, ColorTheme, DEFAULT_PROMPT, GREEN, NumberFormat, RED, YELLOW, clear_sequence,
+        color_code, format_in, format_number, format_result, format_with_precision, json_error,
+        json_explain, json_result, resolve_prompt, use_ansi,
+    };
+    use crate::error::{CalcError, EvalError, ParseError};
+
+    #[test]
+    fn test_format_number_integers() {
+        assert_eq!(format_number(5.0), "5");
+        assert_eq!(format_number(-42.0), "-42");
+        assert_eq!(format_number(0.0), "0");
+        assert_eq!(format_number(-0.0), "0");
+        assert_eq!(format_number(1e15), "1000000000000000");
+    }
+
+    #[test]
+    fn test_format_number_fractions() {
+        assert_eq!(format_number(2.5), "2.5");
+        assert_eq!(format_number(-0.125), "-0.125");
+        assert_eq!(format_number(0.1 + 0.2), "0.3");
+        assert_eq!(format_number(1.0 / 3.0), "0.3333333333");
+        assert_eq!(format_number(12345.678901234), "12345.6789");
+        assert_eq!(format_number(0.000012345), "0.000012345");
+    }
+
+    #[test]
+    fn test_format_number_non_finite() {
+        assert_eq!(format_number(f64::INFINITY), "inf");
+        assert_eq!(format_number(f64::NAN), "NaN");
+    }
+
+    #[test]
+    fn test_format_with_precision() {
+        assert_eq!(format_with_precision(2.0 / 3.0, Some(2)), "0.67");
+        assert_eq!(format_with_precision(5.0, Some(2)), "5.00");
+        assert_eq!(format_with_precision(2.5, Some(0)), "2");
+        assert_eq!(format_with_precision(3.7, Some(0)), "4");
+        assert_eq!(format_with_precision(1.0 / 3.0, None), "0.3333333333");
+        assert_eq!(format_with_precision(f64::INFINITY, Some(2)), "inf");
+    }
+
+    #[test]
+    fn test_format_in() {
+        let (dec, hex, bin) = (
+            NumberFormat::Decimal,
+            NumberFormat::Hex,
+            NumberFormat::Binary,
+        );
+        assert_eq!(format_in(255.0, None, hex), "0xff");
+        assert_eq!(format_in(10.0, None, bin), "0b1010");
+        assert_eq!(format_in(-5.0, None, bin), "-0b101");
+        assert_eq!(format_in(0.0, Some(2), hex), "0x0");
+        assert_eq!(format_in(255.0, Some(1), dec), "255.0");
+        assert_eq!(
+            format_in(2.5, None, hex),
+            "2.5 (не целое число, показано в десятичной записи)"
+        );
+        assert_eq!(
+            format_in(f64::INFINITY, None, bin),
+            "inf (не целое число, показано в десятичной записи)"
+        );
+
+        assert_eq!(NumberFormat::from_name("hex"), Some(hex));
+        assert_eq!(NumberFormat::from_name("oct"), None);
+    }
+
+    #[test]
+    fn test_format_result() {
+        let dec = NumberFormat::Decimal;
+        assert_eq!(format_result(2.5, None, dec, false), "2.5");
+        assert_eq!(format_result(1.0 / 3.0, Some(2), dec, false), "0.33");
+        assert_eq!(
+            format_result(5.0, None, dec, true),
+            "\x1b[32mРезультат: 5\x1b[0m"
+        );
+        assert_eq!(format_result(255.0, None, NumberFormat::Hex, false), "0xff");
+    }
+
+    #[test]
+    fn test_use_ansi() {
+        assert!(use_ansi(false, false, false));
+        assert!(use_ansi(true, true, false));
+        assert!(!use_ansi(true, false, false));
+
+        // NO_COLOR или --no-color отключают цвета всегда
+        assert!(!use_ansi(false, false, true));
+        assert!(!use_ansi(true, true, true));
+    }
+
+    #[test]
+    fn test_color_code() {
+        assert_eq!(color_code("red"), Some(RED));
+        assert_eq!(color_code("Blue"), Some("\x1b[34m"));
+        assert_eq!(color_code(" cyan "), Some("\x1b[36m"));
+        assert_eq!(color_code("pink"), None);
+        assert_eq!(color_code(""), None);
+    }
+
+    #[test]
+    fn test_color_theme_from_names() {
+        assert_eq!(
+            ColorTheme::from_names(None, None, None),
+            ColorTheme::default()
+        );
+
+        let theme = ColorTheme::from_names(Some("blue"), Some("pink"), Some("white"));
+        assert_eq!(
+            theme,
+            ColorTheme {
+                result: "\x1b[34m",
+                error: RED,
+                prompt: "\x1b[37m",
+            }
+        );
+        assert_eq!(ColorTheme::default().result, GREEN);
+        assert_eq!(ColorTheme::default().prompt, YELLOW);
+    }
+
+    #[test]
+    fn test_clear_sequence() {
+        assert_eq!(clear_sequence(true), CLEAR_SCREEN);
+        assert_eq!(clear_sequence(false), "");
+    }
+
+    #[test]
+    fn test_resolve_prompt() {
+        let s = |v: &str| Some(v.to_string());
+        assert_eq!(resolve_prompt(s("> "), s("calc> ")), "> ");
+        assert_eq!(resolve_prompt(None, s("calc> ")), "calc> ");
+        assert_eq!(resolve_prompt(None, None), DEFAULT_PROMPT);
+    }
+
+    #[test]
+    fn test_json_result() {
+        assert_eq!(json_result(5.0), r#"{"ok":true,"result":5.0}"#);
+        assert_eq!(json_result(-2.5), r#"{"ok":true,"result":-2.5}"#);
+        assert_eq!(json_result(f64::INFINITY), r#"{"ok":true,"result":null}"#);
+    }
+
+    #[test]
+    fn test_json_explain() {
+        let steps = ["2 → [2]".to_string(), "- → [-2]".to_string()];
+        assert_eq!(
+            json_explain(-2.0, &steps),
+            r#"{"ok":true,"result":-2.0,"steps":["2 → [2]","- → [-2]"]}"#
+        );
+        assert_eq!(
+            json_explain(f64::INFINITY, &[]),
+            r#"{"ok":true,"result":null,"steps":[]}"#
+        );
+    }
+
+    #[test]
+    fn test_json_error() {
+        assert_eq!(
+            json_error(&CalcError::Eval(EvalError::DivideByZero)),
+            r#"{"ok":false,"error":"Деление на 0."}"#
+        );
+        assert_eq!(
+            json_error(&CalcError::Parse(ParseError::InvalidTokenAt {
+                ch: '"',
+                pos: 2
+            })),
+            r#"{"ok":false,"error":"Некорректный символ '\"' в позиции 2"}"#
+        );
     }
 }
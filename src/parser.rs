@@ -1,118 +1,904 @@
-use crate::error::CalcError;
+use std::collections::HashMap;
+use std::f64::consts;
+use std::fmt;
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::CharIndices;
 
-#[derive(Debug, PartialEq)]
+use crate::error::ParseError;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Token {
     Number(f64),
     Plus,
     Minus,
     UnaryMinus,
+    UnaryPlus,
     Multiply,
     Divide,
+    Modulo,
     LParen,
     RParen,
+    Comma,
     Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    Factorial,
+    Percent,
+    Function(String),
+    Ans,
+    // Ссылка на N-й результат сеанса: "$1"
+    HistoryRef(usize),
+    Variable(String),
+}
+
+// Выводит токен так, как он записывается в выражении: "2", "+", "sin"
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Plus | Token::UnaryPlus => write!(f, "+"),
+            Token::Minus | Token::UnaryMinus => write!(f, "-"),
+            Token::Multiply => write!(f, "*"),
+            Token::Divide => write!(f, "/"),
+            Token::Modulo | Token::Percent => write!(f, "%"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+            Token::Power => write!(f, "^"),
+            Token::BitAnd => write!(f, "&"),
+            Token::BitOr => write!(f, "|"),
+            Token::BitXor => write!(f, "^^"),
+            Token::Less => write!(f, "<"),
+            Token::LessEqual => write!(f, "<="),
+            Token::Greater => write!(f, ">"),
+            Token::GreaterEqual => write!(f, ">="),
+            Token::Equal => write!(f, "=="),
+            Token::NotEqual => write!(f, "!="),
+            Token::Factorial => write!(f, "!"),
+            Token::Function(name) | Token::Variable(name) => write!(f, "{}", name),
+            Token::Ans => write!(f, "{}", ANS),
+            Token::HistoryRef(n) => write!(f, "${}", n),
+        }
+    }
+}
+
+// Диапазон байтов токена в исходной строке
+pub type Span = Range<usize>;
+
+// Токен вместе с его положением в исходной строке
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned {
+    pub token: Token,
+    pub span: Span,
 }
 
+impl Spanned {
+    pub fn new(token: Token, span: Span) -> Self {
+        Spanned { token, span }
+    }
+
+    /// Возвращает текст токена для вывода: числовой литерал — так, как он
+    /// записан во входной строке ("2.50", "1e3"), остальное — через Display.
+    /// Пример: "2.50 + pi" → "2.50", "+", "3.141592653589793"
+    pub fn lexeme(&self, input: &str) -> String {
+        match (&self.token, input.get(self.span.clone())) {
+            (Token::Number(_), Some(text))
+                if text.starts_with(|c: char| c.is_ascii_digit() || c == '.') =>
+            {
+                text.to_string()
+            }
+            _ => self.token.to_string(),
+        }
+    }
+}
+
+// Поддерживаемые встроенные функции
+pub const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "asin", "acos", "atan", "atan2", "sinh", "cosh",
+    "tanh", "sqrt", "exp", "ln", "log", "abs", "sign", "trunc", "floor", "ceil", "round", "cbrt",
+    "root", "min", "max", "gcd", "lcm", "rand",
+];
+
+/// Возвращает число аргументов встроенной функции.
+pub fn function_arity(name: &str) -> usize {
+    match name {
+        "min" | "max" | "root" | "gcd" | "lcm" | "rand" | "atan2" => 2,
+        _ => 1,
+    }
+}
+
+// Золотое сечение (1 + √5) / 2
+const PHI: f64 = 1.618_033_988_749_895;
+
+// Именованные константы
+pub const CONSTANTS: &[(&str, f64)] = &[
+    ("pi", consts::PI),
+    ("e", consts::E),
+    ("tau", consts::TAU),
+    ("phi", PHI),
+];
+
+// Идентификатор предыдущего результата
+pub const ANS: &str = "ans";
+
 impl Token {
+    /// Проверяет, является ли токен бинарным оператором.
+    pub fn is_binary_operator(&self) -> bool {
+        matches!(
+            self,
+            Token::Plus
+                | Token::Minus
+                | Token::Multiply
+                | Token::Divide
+                | Token::Modulo
+                | Token::Power
+                | Token::BitAnd
+                | Token::BitOr
+                | Token::BitXor
+                | Token::Less
+                | Token::LessEqual
+                | Token::Greater
+                | Token::GreaterEqual
+                | Token::Equal
+                | Token::NotEqual
+        )
+    }
+
+    /// Проверяет, группируется ли оператор справа налево: "2^3^2" → 2^(3^2).
+    pub fn is_right_associative(&self) -> bool {
+        matches!(self, Token::Power)
+    }
+
+    /// Приоритет токена по стандартной таблице `PrecedenceTable::default()`.
     pub fn precedence(&self) -> u8 {
+        PrecedenceTable::default().precedence(self)
+    }
+
+    /// Проверяет, выполняется ли оператор `self`, стоящий слева, раньше
+    /// оператора `other` справа. Учитывает приоритет и ассоциативность.
+    /// Пример: "*" связывает сильнее "+", "-" сильнее "-", но "^" не сильнее "^"
+    pub fn binds_tighter_than(&self, other: &Token) -> bool {
+        PrecedenceTable::default().binds_tighter(self, other)
+    }
+}
+
+/// Таблица приоритетов операторов: чем больше число, тем сильнее связывает
+/// оператор. Скобки, функции и постфиксные операторы не настраиваются.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrecedenceTable {
+    // "<", "<=", ">", ">=", "==" и "!="
+    pub comparison: u8,
+    pub bit_or: u8,
+    pub bit_xor: u8,
+    pub bit_and: u8,
+    // "+" и "-"
+    pub additive: u8,
+    // "*", "/" и "%"
+    pub multiplicative: u8,
+    // Унарные "+" и "-"
+    pub unary: u8,
+    pub power: u8,
+}
+
+// Побитовые операции связывают слабее арифметических, как в C: "6 & 3 + 1" → 6 & 4.
+// Сравнения связывают слабее всех, как в Python: "x & 1 == 1" → (x & 1) == 1
+impl Default for PrecedenceTable {
+    fn default() -> Self {
+        PrecedenceTable {
+            comparison: 2,
+            bit_or: 3,
+            bit_xor: 4,
+            bit_and: 5,
+            additive: 6,
+            multiplicative: 7,
+            unary: 8,
+            power: 9,
+        }
+    }
+}
+
+impl PrecedenceTable {
+    /// Проверяет по этой таблице, выполняется ли левый оператор раньше правого.
+    /// При равном приоритете левый выполняется раньше только для
+    /// левоассоциативных операций: "8 - 3 - 2" → (8 - 3) - 2, а "2^3^2" → 2^(3^2).
+    pub fn binds_tighter(&self, left: &Token, right: &Token) -> bool {
+        let (left_precedence, right_precedence) = (self.precedence(left), self.precedence(right));
+        left_precedence > right_precedence
+            || (left_precedence == right_precedence && !right.is_right_associative())
+    }
+
+    /// Возвращает приоритет токена по этой таблице.
+    pub fn precedence(&self, token: &Token) -> u8 {
+        match token {
+            Token::Number(_) | Token::Ans | Token::HistoryRef(_) | Token::Variable(_) => 0,
+            Token::LParen | Token::RParen | Token::Comma => 1,
+            Token::Less
+            | Token::LessEqual
+            | Token::Greater
+            | Token::GreaterEqual
+            | Token::Equal
+            | Token::NotEqual => self.comparison,
+            Token::BitOr => self.bit_or,
+            Token::BitXor => self.bit_xor,
+            Token::BitAnd => self.bit_and,
+            Token::Plus | Token::Minus => self.additive,
+            Token::Multiply | Token::Divide | Token::Modulo => self.multiplicative,
+            Token::UnaryMinus | Token::UnaryPlus => self.unary,
+            Token::Power => self.power,
+            Token::Function(_) => 10,
+            Token::Factorial | Token::Percent => 11,
+        }
+    }
+}
+
+// Возвращает основание системы счисления для литералов вида "0x..." и "0b..."
+fn radix_prefix(s: &str) -> Option<u32> {
+    match s.get(..2) {
+        Some("0x") | Some("0X") => Some(16),
+        Some("0b") | Some("0B") => Some(2),
+        _ => None,
+    }
+}
+
+fn get_fnum(s: &str) -> Result<f64, ParseError> {
+    if let Some(radix) = radix_prefix(s) {
+        return match i64::from_str_radix(&s[2..], radix) {
+            Ok(inum) => Ok(inum as f64),
+            Err(_) => Err(ParseError::InvalidToken(s.to_string())),
+        };
+    }
+
+    // Число без единой цифры: ".", ".."
+    if !s.chars().any(|c| c.is_ascii_digit()) {
+        return Err(ParseError::InvalidToken(format!(
+            "Некорректное число: '{}'",
+            s
+        )));
+    }
+
+    let literal = s.trim();
+    literal
+        .parse::<f64>()
+        .map_err(|source| ParseError::InvalidNumber {
+            literal: literal.to_string(),
+            source,
+        })
+}
+
+// Убирает разделители разрядов "_" из числа: "1_000" → "1000".
+// Подчёркивание допустимо только между двумя цифрами.
+fn strip_underscores(s: &str) -> Result<String, ParseError> {
+    let is_radix = radix_prefix(s).is_some();
+    let is_digit = |c: Option<char>| {
+        c.is_some_and(|c| {
+            if is_radix {
+                c.is_ascii_hexdigit()
+            } else {
+                c.is_ascii_digit()
+            }
+        })
+    };
+
+    let chars: Vec<char> = s.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|j| chars[j]);
+        if c == '_' && !(is_digit(prev) && is_digit(chars.get(i + 1).copied())) {
+            return Err(ParseError::InvalidToken(s.to_string()));
+        }
+    }
+
+    Ok(s.replace('_', ""))
+}
+
+/// Проверяет, занято ли имя константой, функцией или `ans`.
+pub fn is_reserved(name: &str) -> bool {
+    name == ANS || FUNCTIONS.contains(&name) || CONSTANTS.iter().any(|(c, _)| *c == name)
+}
+
+// Преобразует имя идентификатора в токен функции, константы или переменной
+fn get_ident(s: &str, vars: Option<&HashMap<String, f64>>) -> Result<Token, ParseError> {
+    if let Some(&(_, value)) = CONSTANTS.iter().find(|(name, _)| *name == s) {
+        return Ok(Token::Number(value));
+    }
+
+    if s == ANS {
+        return Ok(Token::Ans);
+    }
+
+    // При заданных переменных неизвестное имя считается неизвестной переменной
+    match vars {
+        _ if FUNCTIONS.contains(&s) => Ok(Token::Function(s.to_string())),
+        Some(vars) if vars.contains_key(s) => Ok(Token::Variable(s.to_string())),
+        Some(_) => Err(ParseError::UnknownVariable(s.to_string())),
+        None => Err(ParseError::InvalidToken(format!(
+            "Неизвестный идентификатор: '{}'",
+            s
+        ))),
+    }
+}
+
+/// Разделитель целой и дробной части числа
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DecimalSeparator {
+    #[default]
+    Dot,
+    Comma,
+}
+
+impl DecimalSeparator {
+    fn as_char(self) -> char {
         match self {
-            Token::Number(_) => 0,
-            Token::LParen | Token::RParen => 1,
-            Token::Plus | Token::Minus => 2,
-            Token::Multiply | Token::Divide => 3,
-            Token::UnaryMinus => 4,
-            Token::Power => 5,
+            DecimalSeparator::Dot => '.',
+            DecimalSeparator::Comma => ',',
         }
     }
+
+    // Разделитель аргументов функции: при десятичной запятой — точка с запятой
+    fn arg_separator(self) -> char {
+        match self {
+            DecimalSeparator::Dot => ',',
+            DecimalSeparator::Comma => ';',
+        }
+    }
+}
+
+// Вид последнего выданного токена, от которого зависит разбор следующего
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Last {
+    // Число, `ans` или переменная
+    Operand,
+    // Закрывающая скобка или постфиксный оператор
+    Closing,
+    // Имя функции
+    Function,
+    // Открывающая скобка или оператор
+    Prefix,
 }
 
-fn get_fnum(s: &str) -> Result<f64, CalcError> {
-    match s.trim().parse::<f64>() {
-        Ok(fnum) => Ok(fnum),
-        Err(_) => Err(CalcError::InvalidToken(s.to_string())),
+impl Last {
+    fn of(token: &Token) -> Last {
+        match token {
+            Token::Number(_) | Token::Ans | Token::HistoryRef(_) | Token::Variable(_) => {
+                Last::Operand
+            }
+            Token::RParen | Token::Factorial | Token::Percent => Last::Closing,
+            Token::Function(_) => Last::Function,
+            _ => Last::Prefix,
+        }
     }
 }
 
-// Разбивает строку на токены.
-// Пример: "2 + 3" → [Token::Number(2.0), Token::Plus, Token::Number(3.0)]
-pub fn tokenize(input: &str) -> Result<Vec<Token>, CalcError> {
-    let mut tokens: Vec<Token> = Vec::new();
-    let mut num_buffer = String::new();
+// Суффиксы углов и число единиц в градусе
+const DMS_UNITS: [(char, f64); 3] = [('d', 1.0), ('m', 60.0), ('s', 3600.0)];
+
+/// Потоковый лексер: лениво выдаёт токены по мере чтения строки.
+/// Пример: Lexer::new("2 + 3") → Number(2.0), Plus, Number(3.0)
+pub struct Lexer<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    vars: Option<&'a HashMap<String, f64>>,
+    separator: DecimalSeparator,
+    // Читать ли углы в градусах, минутах и секундах: "90d30m"
+    dms: bool,
+    // Номер следующего символа (не байта) во входной строке
+    pos: usize,
+    last: Option<Last>,
+    // Токен, перед которым выдано неявное умножение
+    pending: Option<Spanned>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Lexer {
+            input,
+            chars: input.char_indices().peekable(),
+            vars: None,
+            separator: DecimalSeparator::default(),
+            dms: false,
+            pos: 0,
+            last: None,
+            pending: None,
+        }
+    }
+
+    /// Лексер, распознающий имена из `vars` как переменные.
+    pub fn with_vars(input: &'a str, vars: &'a HashMap<String, f64>) -> Self {
+        Lexer {
+            vars: Some(vars),
+            ..Lexer::new(input)
+        }
+    }
+
+    /// Задаёт разделитель дробной части: с `DecimalSeparator::Comma`
+    /// число "3,14" читается как 3.14, а точка становится недопустимым символом.
+    pub fn with_separator(mut self, separator: DecimalSeparator) -> Self {
+        self.separator = separator;
+        self
+    }
 
-    for c in input.chars() {
-        if c.is_ascii_digit() || c == '.' {
-            num_buffer.push(c);
-            continue;
-        } else if !num_buffer.is_empty() {
-            let num = get_fnum(&num_buffer)?;
-            tokens.push(Token::Number(num));
-            num_buffer.clear();
+    /// Включает углы в градусах, минутах и секундах: "90d30m" читается
+    /// как 90.5, "1d" — как 1. По умолчанию выключено.
+    pub fn with_dms(mut self, enabled: bool) -> Self {
+        self.dms = enabled;
+        self
+    }
+
+    /// Возвращает следующий токен вместе с его положением в строке.
+    pub fn next_spanned(&mut self) -> Option<Result<Spanned, ParseError>> {
+        if let Some(spanned) = self.pending.take() {
+            return Some(Ok(self.emit(spanned)));
         }
 
-        if c.is_whitespace() {
-            continue;
+        while self.chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {
+            self.pos += 1;
         }
 
+        let &(offset, c) = self.chars.peek()?;
+        let result = if c.is_ascii_digit() || c == self.separator.as_char() {
+            self.scan_number(offset)
+                .map(|token| self.implicit_mul(token, false))
+        } else if c.is_alphabetic() {
+            self.scan_ident(offset)
+                .map(|token| self.implicit_mul(token, true))
+        } else if c == '$' && self.history_ref_follows() {
+            self.scan_history_ref(offset)
+                .map(|token| self.implicit_mul(token, true))
+        } else {
+            self.scan_symbol(offset, c)
+        };
+
+        Some(result.map(|spanned| self.emit(spanned)))
+    }
+
+    // Запоминает вид выданного токена
+    fn emit(&mut self, spanned: Spanned) -> Spanned {
+        self.last = Some(Last::of(&spanned.token));
+        spanned
+    }
+
+    // Возвращает байтовое смещение следующего символа
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.input.len(), |&(i, _)| i)
+    }
+
+    // Забирает следующий символ, если он подходит под условие
+    fn bump_if(&mut self, pred: impl FnOnce(char) -> bool) -> Option<char> {
+        let (_, c) = self.chars.next_if(|&(_, c)| pred(c))?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    // Вставляет неявное умножение перед операндом: "2(3+4)", "3pi", "(1+2)(3+4)".
+    // Два числовых литерала подряд ("2 3") не перемножаются, поэтому для них
+    // `after_number` равен false. Вставленный токен получает пустой диапазон.
+    fn implicit_mul(&mut self, spanned: Spanned, after_number: bool) -> Spanned {
+        let needs_mul = match self.last {
+            Some(Last::Closing) => true,
+            Some(Last::Operand) => after_number,
+            _ => false,
+        };
+        if !needs_mul {
+            return spanned;
+        }
+
+        let at = spanned.span.start;
+        self.pending = Some(spanned);
+        Spanned::new(Token::Multiply, at..at)
+    }
+
+    // Проверяет, стоит ли знак "+" или "-" в позиции унарного оператора,
+    // то есть в начале выражения, после открывающей скобки или другого оператора.
+    fn is_unary_position(&self) -> bool {
+        matches!(self.last, None | Some(Last::Prefix))
+    }
+
+    // Проверяет, начинается ли с текущего символа показатель степени:
+    // "e5", "E-3". Иначе "e" после числа — константа: "2e" → 2 * e.
+    fn exponent_follows(&self) -> bool {
+        let mut rest = self.chars.clone().map(|(_, c)| c);
+        if !matches!(rest.next(), Some('e' | 'E')) {
+            return false;
+        }
+        match rest.next() {
+            Some('+' | '-') => rest.next().is_some_and(|c| c.is_ascii_digit()),
+            Some(c) => c.is_ascii_digit(),
+            None => false,
+        }
+    }
+
+    // Читает число: десятичное, экспоненциальное ("2.5E-3") или с префиксом ("0xFF")
+    fn scan_number(&mut self, start: usize) -> Result<Spanned, ParseError> {
+        let separator = self.separator.as_char();
+        let mut num_buffer = String::new();
+        loop {
+            let is_radix = radix_prefix(&num_buffer).is_some();
+            let has_exp = !is_radix && num_buffer.contains(['e', 'E']);
+            let exp_follows = self.exponent_follows();
+            let after_exp = !is_radix && num_buffer.ends_with(['e', 'E']);
+            let is_zero = num_buffer == "0";
+            let is_empty = num_buffer.is_empty();
+
+            let next = self.bump_if(|c| {
+                (is_radix && c.is_ascii_alphanumeric())
+                    || (is_zero && matches!(c, 'x' | 'X' | 'b' | 'B'))
+                    || c.is_ascii_digit()
+                    || c == separator
+                    || (!is_radix && !is_empty && !has_exp && exp_follows)
+                    || (after_exp && matches!(c, '+' | '-'))
+                    || (!is_empty && c == '_')
+            });
+            match next {
+                Some(c) if c == separator => num_buffer.push('.'),
+                Some(c) => num_buffer.push(c),
+                None => break,
+            }
+        }
+
+        let mut num = get_fnum(&strip_underscores(&num_buffer)?)?;
+        if self.dms && radix_prefix(&num_buffer).is_none() {
+            num = self.scan_dms(num, start)?;
+        }
+        Ok(Spanned::new(Token::Number(num), start..self.offset()))
+    }
+
+    // Продолжает число суффиксами градусов, минут и секунд: "90d30m15s".
+    // Части идут в порядке d, m, s; без суффикса число возвращается как есть
+    fn scan_dms(&mut self, mut number: f64, start: usize) -> Result<f64, ParseError> {
+        let mut degrees = 0.0;
+        let mut next_unit = 0;
+        while let Some(unit) = self.dms_unit(next_unit) {
+            self.bump_if(|_| true);
+            degrees += number / DMS_UNITS[unit].1;
+            next_unit = unit + 1;
+
+            if self.chars.peek().is_none_or(|&(_, c)| !c.is_ascii_digit()) {
+                return Ok(degrees);
+            }
+            let mut buffer = String::new();
+            while let Some(c) = self.bump_if(|c| c.is_ascii_digit() || c == '.') {
+                buffer.push(c);
+            }
+            number = get_fnum(&buffer)?;
+        }
+
+        if next_unit == 0 {
+            return Ok(number);
+        }
+        // Последняя часть осталась без суффикса или суффиксы идут не по порядку
+        Err(ParseError::InvalidToken(format!(
+            "Некорректный угол: '{}'",
+            &self.input[start..self.offset()]
+        )))
+    }
+
+    // Номер суффикса d/m/s в следующей позиции, не меньший `from`. Суффикс,
+    // за которым идёт буква, — начало идентификатора: "2max(1, 2)"
+    fn dms_unit(&self, from: usize) -> Option<usize> {
+        let mut rest = self.chars.clone().map(|(_, c)| c);
+        let suffix = rest.next()?;
+        if rest.next().is_some_and(|c| c.is_alphabetic() || c == '_') {
+            return None;
+        }
+        DMS_UNITS
+            .iter()
+            .position(|&(c, _)| c == suffix)
+            .filter(|&unit| unit >= from)
+    }
+
+    // Проверяет, что за "$" сразу идёт цифра: "$1"
+    fn history_ref_follows(&self) -> bool {
+        let mut rest = self.chars.clone().map(|(_, c)| c).skip(1);
+        rest.next().is_some_and(|c| c.is_ascii_digit())
+    }
+
+    // Читает ссылку на результат сеанса: "$2" → HistoryRef(2)
+    fn scan_history_ref(&mut self, start: usize) -> Result<Spanned, ParseError> {
+        self.bump_if(|c| c == '$');
+        let mut digits = String::new();
+        while let Some(c) = self.bump_if(|c| c.is_ascii_digit()) {
+            digits.push(c);
+        }
+
+        match digits.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(Spanned::new(Token::HistoryRef(n), start..self.offset())),
+            _ => Err(ParseError::InvalidToken(format!(
+                "Некорректная ссылка на результат: '${}', номера начинаются с 1",
+                digits
+            ))),
+        }
+    }
+
+    // Читает идентификатор: имя функции, константы или переменной
+    fn scan_ident(&mut self, start: usize) -> Result<Spanned, ParseError> {
+        let mut ident_buffer = String::new();
+        while let Some(c) = self.bump_if(|c| c.is_alphanumeric() || c == '_') {
+            ident_buffer.push(c);
+        }
+
+        let token = get_ident(&ident_buffer, self.vars)?;
+        Ok(Spanned::new(token, start..self.offset()))
+    }
+
+    // Проверяет, стоит ли знак "%" в позиции процента: после операнда и без
+    // операнда справа ("15%", "200 * 15% + 1"). Иначе это остаток от деления ("10 % 3").
+    // Знак, прижатый к следующему операнду, считается унарным: "10 % -3" — остаток.
+    fn is_percent_position(&self, offset: usize) -> bool {
+        let mut rest = self.input[offset + 1..].trim_start().chars();
+        let next_is_operator = match rest.next() {
+            None | Some('*' | '/' | '^' | '%' | '!' | '&' | '|' | ')' | ',' | ';') => true,
+            Some('+' | '-') => rest.next().is_none_or(char::is_whitespace),
+            _ => false,
+        };
+        matches!(self.last, Some(Last::Operand | Last::Closing)) && next_is_operator
+    }
+
+    // Читает односимвольный оператор или скобку
+    fn scan_symbol(&mut self, offset: usize, c: char) -> Result<Spanned, ParseError> {
+        let pos = self.pos;
+        self.bump_if(|_| true);
+        let span = offset..offset + c.len_utf8();
+
         let token = match c {
+            '^' if self.bump_if(|c| c == '^').is_some() => {
+                return Ok(Spanned::new(Token::BitXor, offset..offset + 2));
+            }
             '^' => Token::Power,
+            '&' => Token::BitAnd,
+            '|' => Token::BitOr,
+            '+' if self.is_unary_position() => Token::UnaryPlus,
             '+' => Token::Plus,
-            '-' => {
-                // Проверяем на символ на унарный минус
-                let is_unary = match tokens.last() {
-                    None => true, // Начало выражения: "-5"
-                    Some(Token::LParen)
-                    | Some(Token::Plus)
-                    | Some(Token::Minus)
-                    | Some(Token::Multiply)
-                    | Some(Token::Divide)
-                    | Some(Token::Power) => true,
-                    _ => false,
-                };
-                if is_unary {
-                    Token::UnaryMinus
-                } else {
-                    Token::Minus
-                }
+            '-' if self.is_unary_position() => Token::UnaryMinus,
+            '-' => Token::Minus,
+            // "**" — синоним "^" для привыкших к Python
+            '*' if self.bump_if(|c| c == '*').is_some() => {
+                return Ok(Spanned::new(Token::Power, offset..offset + 2));
             }
             '*' => Token::Multiply,
             '/' => Token::Divide,
-            '(' => Token::LParen,
-            ')' => Token::RParen,
-            _ => {
-                return Err(CalcError::InvalidToken(format!(
-                    "Некорректный символ в выражении: '{}'",
-                    c
-                )));
+            '%' if self.is_percent_position(offset) => Token::Percent,
+            '%' => Token::Modulo,
+            // Двухсимвольные операторы выбираются жадно: "<=" — один токен, "< =" — нет
+            '<' | '>' | '=' | '!' if self.bump_if(|c| c == '=').is_some() => {
+                let token = match c {
+                    '<' => Token::LessEqual,
+                    '>' => Token::GreaterEqual,
+                    '=' => Token::Equal,
+                    _ => Token::NotEqual,
+                };
+                return Ok(Spanned::new(token, offset..offset + 2));
             }
+            '<' => Token::Less,
+            '>' => Token::Greater,
+            '!' => Token::Factorial,
+            '(' => return Ok(self.implicit_mul(Spanned::new(Token::LParen, span), true)),
+            ')' => Token::RParen,
+            c if c == self.separator.arg_separator() => Token::Comma,
+            _ => return Err(ParseError::InvalidTokenAt { ch: c, pos }),
+        };
+        Ok(Spanned::new(token, span))
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<Token, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_spanned()
+            .map(|result| result.map(|spanned| spanned.token))
+    }
+}
+
+// Разбивает строку на токены.
+// Пример: "2 + 3" → [Token::Number(2.0), Token::Plus, Token::Number(3.0)]
+pub fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    Lexer::new(input).collect()
+}
+
+// Разбивает строку на токены, не останавливаясь на ошибках: некорректные
+// символы пропускаются, а все найденные ошибки возвращаются вместе с токенами.
+// Пример: "2 @ 3" → ([Number(2.0), Number(3.0)], [InvalidTokenAt { ch: '@', pos: 2 }])
+pub fn tokenize_all(input: &str) -> (Vec<Token>, Vec<ParseError>) {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    for result in Lexer::new(input) {
+        match result {
+            Ok(token) => tokens.push(token),
+            Err(error) => errors.push(error),
+        }
+    }
+    (tokens, errors)
+}
+
+// Разбивает строку на токены и описывает, как разрешены неоднозначные знаки:
+// "+" или "-" сразу после бинарного оператора читается как унарный.
+// Пример: "2 - -3" → ([2, -, -, 3], ["Знак '-' на позиции 4 после оператора '-' прочитан как унарный минус"])
+pub fn tokenize_with_warnings(input: &str) -> Result<(Vec<Token>, Vec<String>), ParseError> {
+    let mut lexer = Lexer::new(input);
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut warnings = Vec::new();
+
+    while let Some(spanned) = lexer.next_spanned() {
+        let Spanned { token, span } = spanned?;
+        let kind = match token {
+            Token::UnaryMinus => Some("унарный минус"),
+            Token::UnaryPlus => Some("унарный плюс"),
+            _ => None,
         };
+        if let (Some(kind), Some(prev)) = (kind, tokens.last())
+            && prev.is_binary_operator()
+        {
+            warnings.push(format!(
+                "Знак '{}' на позиции {} после оператора '{}' прочитан как {}",
+                token,
+                input[..span.start].chars().count(),
+                prev,
+                kind
+            ));
+        }
         tokens.push(token);
     }
 
-    if !num_buffer.is_empty() {
-        let num = get_fnum(&num_buffer)?;
-        tokens.push(Token::Number(num));
+    Ok((tokens, warnings))
+}
+
+// Разбивает строку на токены с заданным разделителем дробной части.
+// Пример: "3,14" при DecimalSeparator::Comma → [Token::Number(3.14)]
+pub fn tokenize_with_separator(
+    input: &str,
+    separator: DecimalSeparator,
+) -> Result<Vec<Token>, ParseError> {
+    Lexer::new(input).with_separator(separator).collect()
+}
+
+// Разбивает строку на токены, распознавая имена из `vars` как переменные.
+// Пример: "x + 1" при x = 2 → [Token::Variable("x"), Token::Plus, Token::Number(1.0)]
+pub fn tokenize_with_vars(
+    input: &str,
+    vars: &HashMap<String, f64>,
+) -> Result<Vec<Token>, ParseError> {
+    Lexer::with_vars(input, vars).collect()
+}
+
+// Разбивает строку на токены, сохраняя положение каждого токена в строке.
+// Если переданы переменные, неизвестные имена считаются неизвестными переменными.
+// Пример: "1 / 0" → [Number(1.0) @ 0..1, Divide @ 2..3, Number(0.0) @ 4..5]
+pub fn tokenize_spanned(
+    input: &str,
+    vars: Option<&HashMap<String, f64>>,
+) -> Result<Vec<Spanned>, ParseError> {
+    let mut lexer = Lexer {
+        vars,
+        ..Lexer::new(input)
+    };
+    std::iter::from_fn(|| lexer.next_spanned()).collect()
+}
+
+/// Проверяет корректность скобок. В ошибке указывается номер токена лишней
+/// закрывающей скобки или первой незакрытой открывающей.
+pub fn validate_parens<'a>(tokens: impl IntoIterator<Item = &'a Token>) -> Result<(), ParseError> {
+    // Номера токенов ещё не закрытых скобок
+    let mut open: Vec<usize> = Vec::new();
+    for (i, token) in tokens.into_iter().enumerate() {
+        match token {
+            Token::LParen => open.push(i),
+            Token::RParen if open.pop().is_none() => {
+                return Err(ParseError::UnmatchedParensAt(i));
+            }
+            _ => {}
+        }
+    }
+
+    match open.first() {
+        Some(&i) => Err(ParseError::UnmatchedParensAt(i)),
+        None => Ok(()),
     }
+}
 
-    Ok(tokens)
+/// Дописывает в конец недостающие закрывающие скобки: "(2 + 3" → "(2 + 3)".
+/// Лишние закрывающие скобки не исправляются, их находит `validate_parens`.
+/// Добавленные скобки получают пустой диапазон в конце строки `end`.
+pub fn close_parens(tokens: &mut Vec<Spanned>, end: usize) {
+    let mut depth = 0usize;
+    for spanned in tokens.iter() {
+        match spanned.token {
+            Token::LParen => depth += 1,
+            Token::RParen if depth == 0 => return,
+            Token::RParen => depth -= 1,
+            _ => {}
+        }
+    }
+
+    tokens.extend((0..depth).map(|_| Spanned::new(Token::RParen, end..end)));
 }
 
-/// Проверяет корректность скобок.
-pub fn validate_parens(tokens: &[Token]) -> Result<(), CalcError> {
-    let mut balance = 0;
+// Допустимая по умолчанию глубина вложенности скобок
+pub const MAX_NESTING_DEPTH: usize = 256;
+
+/// Проверяет, что скобки вложены не глубже `max_depth` уровней.
+/// Защищает от чрезмерно вложенных выражений из недоверенного ввода.
+pub fn validate_depth<'a>(
+    tokens: impl IntoIterator<Item = &'a Token>,
+    max_depth: usize,
+) -> Result<(), ParseError> {
+    let mut depth = 0usize;
     for token in tokens {
         match token {
-            Token::LParen => balance += 1,
-            Token::RParen => balance -= 1,
+            Token::LParen => depth += 1,
+            Token::RParen => depth = depth.saturating_sub(1),
             _ => {}
         }
 
-        if balance < 0 {
-            return Err(CalcError::UnmatchedParens);
+        if depth > max_depth {
+            return Err(ParseError::NestingTooDeep(max_depth));
+        }
+    }
+
+    Ok(())
+}
+
+/// Проверяет синтаксис последовательности токенов до построения ОПЗ.
+/// Положение в строке неизвестно, поэтому в ошибке называются операнды.
+pub fn validate_syntax<'a>(tokens: impl IntoIterator<Item = &'a Token>) -> Result<(), ParseError> {
+    check_syntax(tokens.into_iter().map(|token| (token, None)))
+}
+
+/// Проверяет синтаксис токенов с позициями; в ошибке указывается позиция
+/// символа в строке: "1  2" → "... в позиции 3".
+pub fn validate_syntax_spanned(tokens: &[Spanned]) -> Result<(), ParseError> {
+    check_syntax(tokens.iter().map(|t| (&t.token, Some(t.span.start))))
+}
+
+// Общая проверка синтаксиса; у каждого токена может быть позиция в строке
+fn check_syntax<'a>(
+    tokens: impl IntoIterator<Item = (&'a Token, Option<usize>)>,
+) -> Result<(), ParseError> {
+    let is_operand = |t: &Token| {
+        matches!(
+            t,
+            Token::Number(_) | Token::Ans | Token::HistoryRef(_) | Token::Variable(_)
+        )
+    };
+
+    let mut prev: Option<&Token> = None;
+    for (token, pos) in tokens {
+        // Бинарный оператор в начале выражения: "* 3"
+        if prev.is_none() && token.is_binary_operator() {
+            return Err(ParseError::InvalidExpression(format!(
+                "Выражение начинается с оператора '{}'",
+                token
+            )));
+        }
+
+        // Два операнда подряд: "1 2"
+        if let Some(prev) = prev.filter(|t| is_operand(t))
+            && is_operand(token)
+        {
+            return Err(ParseError::InvalidExpression(match pos {
+                Some(pos) => format!("Пропущен оператор между операндами в позиции {}", pos),
+                None => format!(
+                    "Пропущен оператор между операндами '{}' и '{}'",
+                    prev, token
+                ),
+            }));
         }
+        prev = Some(token);
     }
 
-    if balance != 0 {
-        return Err(CalcError::UnmatchedParens);
+    // Бинарный оператор в конце выражения: "3 +"
+    if let Some(token) = prev.filter(|t| t.is_binary_operator()) {
+        return Err(ParseError::InvalidExpression(format!(
+            "Выражение заканчивается оператором '{}'",
+            token
+        )));
     }
 
     Ok(())
@@ -123,6 +909,13 @@ pub fn validate_parens(tokens: &[Token]) -> Result<(), CalcError> {
 mod tests_tokenize {
     use super::*;
 
+    #[test]
+    fn test_tokens_clone() {
+        let tokens = tokenize("max(2, 1) + sin(pi) * 3!").unwrap();
+        let cached = tokens.clone();
+        assert_eq!(cached, tokens);
+    }
+
     #[test]
     fn test_tokenize_simple_expression() {
         let input = "2 + 3";
@@ -155,102 +948,631 @@ mod tests_tokenize {
     }
 
     #[test]
-    fn test_tokenize_invalid_token() {
-        let input = "2 + a";
-        assert!(matches!(tokenize(input), Err(CalcError::InvalidToken(_))));
+    fn test_tokenize_invalid_token() {
+        let input = "2 + a";
+        assert!(matches!(tokenize(input), Err(ParseError::InvalidToken(_))));
+    }
+
+    #[test]
+    fn test_tokenize_invalid_char_position() {
+        let input = "2 + 3 $ 4";
+        assert_eq!(
+            tokenize(input),
+            Err(ParseError::InvalidTokenAt { ch: '$', pos: 6 })
+        );
+
+        let input = "sin(1) # 2";
+        assert_eq!(
+            tokenize(input),
+            Err(ParseError::InvalidTokenAt { ch: '#', pos: 7 })
+        );
+    }
+
+    #[test]
+    fn test_tokenize_spanned() {
+        let tokens = tokenize_spanned("1 / 0", None).unwrap();
+        let expected = vec![
+            Spanned::new(Token::Number(1.0), 0..1),
+            Spanned::new(Token::Divide, 2..3),
+            Spanned::new(Token::Number(0.0), 4..5),
+        ];
+        assert_eq!(tokens, expected);
+
+        // Неявное умножение получает пустой диапазон перед операндом
+        let tokens = tokenize_spanned("12pi", None).unwrap();
+        let expected = vec![
+            Spanned::new(Token::Number(12.0), 0..2),
+            Spanned::new(Token::Multiply, 2..2),
+            Spanned::new(Token::Number(std::f64::consts::PI), 2..4),
+        ];
+        assert_eq!(tokens, expected);
+
+        // Числа выводятся в исходной записи, константы — значением
+        let input = "2.50 * 1e3pi";
+        let lexemes: Vec<String> = tokenize_spanned(input, None)
+            .unwrap()
+            .iter()
+            .map(|t| t.lexeme(input))
+            .collect();
+        assert_eq!(lexemes, ["2.50", "*", "1e3", "*", "3.141592653589793"]);
+
+        // Диапазоны считаются в байтах
+        let tokens = tokenize_spanned("sin(1.5)²", None);
+        assert_eq!(tokens, Err(ParseError::InvalidTokenAt { ch: '²', pos: 8 }));
+        let tokens = tokenize_spanned("\u{a0}(2)", None).unwrap();
+        assert_eq!(tokens[0].span, 2..3);
+    }
+
+    #[test]
+    fn test_tokenize_invalid_number() {
+        let input = "2 + .";
+        assert!(matches!(tokenize(input), Err(ParseError::InvalidToken(_))));
+    }
+
+    #[test]
+    fn test_tokenize_lone_decimal_point() {
+        let malformed = |s: &str| {
+            Err(ParseError::InvalidToken(format!(
+                "Некорректное число: '{}'",
+                s
+            )))
+        };
+        assert_eq!(tokenize("."), malformed("."));
+        assert_eq!(tokenize("2 + ."), malformed("."));
+        assert_eq!(tokenize(".."), malformed(".."));
+        assert_eq!(tokenize("(. + 1)"), malformed("."));
+        assert_eq!(tokenize(".5").unwrap(), vec![Token::Number(0.5)]);
+        assert_eq!(tokenize("5.").unwrap(), vec![Token::Number(5.0)]);
+    }
+
+    #[test]
+    fn test_tokenize_empty_input() {
+        let input = "";
+        let expected: Vec<Token> = vec![];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_single_number() {
+        let input = "42";
+        let expected = vec![Token::Number(42.0)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_single_operator() {
+        // Плюс в начале выражения — унарный
+        let input = "+";
+        let expected = vec![Token::UnaryPlus];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_mixed_tokens() {
+        let input = "1 + -2 * (3 / 4)";
+        let expected = vec![
+            Token::Number(1.0),
+            Token::Plus,
+            Token::UnaryMinus,
+            Token::Number(2.0),
+            Token::Multiply,
+            Token::LParen,
+            Token::Number(3.0),
+            Token::Divide,
+            Token::Number(4.0),
+            Token::RParen,
+        ];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_negative_number() {
+        let input = "-5";
+        let expected = vec![Token::UnaryMinus, Token::Number(5.0)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_unary_plus() {
+        let input = "+5";
+        let expected = vec![Token::UnaryPlus, Token::Number(5.0)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+
+        let input = "2 + +3";
+        let expected = vec![
+            Token::Number(2.0),
+            Token::Plus,
+            Token::UnaryPlus,
+            Token::Number(3.0),
+        ];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_unary_minus_after_operator_and_paren() {
+        let input = "2*-3";
+        let expected = vec![
+            Token::Number(2.0),
+            Token::Multiply,
+            Token::UnaryMinus,
+            Token::Number(3.0),
+        ];
+        assert_eq!(tokenize(input).unwrap(), expected);
+
+        let input = "(-4)";
+        let expected = vec![
+            Token::LParen,
+            Token::UnaryMinus,
+            Token::Number(4.0),
+            Token::RParen,
+        ];
+        assert_eq!(tokenize(input).unwrap(), expected);
+
+        // После закрывающей скобки минус бинарный
+        let input = "(4)-1";
+        let expected = vec![
+            Token::LParen,
+            Token::Number(4.0),
+            Token::RParen,
+            Token::Minus,
+            Token::Number(1.0),
+        ];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_double_unary_minus() {
+        let input = "--5";
+        let expected = vec![Token::UnaryMinus, Token::UnaryMinus, Token::Number(5.0)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_number_with_leading_dot() {
+        let input = ".5";
+        let expected = vec![Token::Number(0.5)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_number_with_trailing_dot() {
+        let input = "5.";
+        let expected = vec![Token::Number(5.0)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_number_with_multiple_dots() {
+        let input = "1.2.3";
+        let err = tokenize(input).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidNumber { ref literal, .. } if literal == "1.2.3"));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn test_tokenize_number_with_invalid_character() {
+        let input = "1a2";
+        assert!(matches!(tokenize(input), Err(ParseError::InvalidToken(_))));
+    }
+
+    #[test]
+    fn test_tokenize_chained_operators() {
+        let input = "1 + - * /";
+        let expected = vec![
+            Token::Number(1.0),
+            Token::Plus,
+            Token::UnaryMinus,
+            Token::Multiply,
+            Token::Divide,
+        ];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_two_char_operators() {
+        assert_eq!(
+            tokenize("1<=2>=3!=4==5**6").unwrap(),
+            vec![
+                Token::Number(1.0),
+                Token::LessEqual,
+                Token::Number(2.0),
+                Token::GreaterEqual,
+                Token::Number(3.0),
+                Token::NotEqual,
+                Token::Number(4.0),
+                Token::Equal,
+                Token::Number(5.0),
+                Token::Power,
+                Token::Number(6.0),
+            ]
+        );
+        assert_eq!(
+            tokenize("1 < 2 > 3").unwrap(),
+            vec![
+                Token::Number(1.0),
+                Token::Less,
+                Token::Number(2.0),
+                Token::Greater,
+                Token::Number(3.0),
+            ]
+        );
+
+        // Через пробел это два отдельных символа, а одиночный "=" недопустим
+        let (tokens, errors) = tokenize_all("1 < = 2");
+        assert_eq!(
+            tokens,
+            vec![Token::Number(1.0), Token::Less, Token::Number(2.0)]
+        );
+        assert_eq!(errors, vec![ParseError::InvalidTokenAt { ch: '=', pos: 4 }]);
+
+        // "3! = 6" — факториал, "3 != 6" — неравенство
+        assert_eq!(
+            tokenize("3! * 2").unwrap(),
+            vec![
+                Token::Number(3.0),
+                Token::Factorial,
+                Token::Multiply,
+                Token::Number(2.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_modulo() {
+        let input = "10 % -3";
+        let expected = vec![
+            Token::Number(10.0),
+            Token::Modulo,
+            Token::UnaryMinus,
+            Token::Number(3.0),
+        ];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_functions() {
+        let input = "sin(0) + cos(x1)";
+        assert!(matches!(tokenize(input), Err(ParseError::InvalidToken(_))));
+
+        let input = "sin(0) * tan(-1)";
+        let expected = vec![
+            Token::Function("sin".to_string()),
+            Token::LParen,
+            Token::Number(0.0),
+            Token::RParen,
+            Token::Multiply,
+            Token::Function("tan".to_string()),
+            Token::LParen,
+            Token::UnaryMinus,
+            Token::Number(1.0),
+            Token::RParen,
+        ];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_constants() {
+        let input = "2 * pi";
+        let expected = vec![
+            Token::Number(2.0),
+            Token::Multiply,
+            Token::Number(std::f64::consts::PI),
+        ];
+        assert_eq!(tokenize(input).unwrap(), expected);
+
+        let input = "e^2";
+        let expected = vec![
+            Token::Number(std::f64::consts::E),
+            Token::Power,
+            Token::Number(2.0),
+        ];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_ans() {
+        let input = "ans * 2";
+        let expected = vec![Token::Ans, Token::Multiply, Token::Number(2.0)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+
+        let input = "2ans";
+        let expected = vec![Token::Number(2.0), Token::Multiply, Token::Ans];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_variables() {
+        let vars = HashMap::from([("x".to_string(), 2.0)]);
+        let input = "2x + x(1)";
+        let expected = vec![
+            Token::Number(2.0),
+            Token::Multiply,
+            Token::Variable("x".to_string()),
+            Token::Plus,
+            Token::Variable("x".to_string()),
+            Token::Multiply,
+            Token::LParen,
+            Token::Number(1.0),
+            Token::RParen,
+        ];
+        assert_eq!(tokenize_with_vars(input, &vars).unwrap(), expected);
+
+        assert_eq!(
+            tokenize_with_vars("y", &vars),
+            Err(ParseError::UnknownVariable("y".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_is_reserved() {
+        assert!(is_reserved("pi"));
+        assert!(is_reserved("tau"));
+        assert!(is_reserved("phi"));
+        assert!(is_reserved("sin"));
+        assert!(is_reserved("ans"));
+        assert!(!is_reserved("x"));
+    }
+
+    #[test]
+    fn test_tokenize_unknown_identifier() {
+        assert!(matches!(tokenize("taux"), Err(ParseError::InvalidToken(_))));
+        assert!(matches!(tokenize("pie"), Err(ParseError::InvalidToken(_))));
     }
 
     #[test]
-    fn test_tokenize_invalid_number() {
-        let input = "2 + .";
-        assert!(matches!(tokenize(input), Err(CalcError::InvalidToken(_))));
+    fn test_tokenize_unknown_function() {
+        let input = "foo(1)";
+        assert!(matches!(tokenize(input), Err(ParseError::InvalidToken(_))));
     }
 
     #[test]
-    fn test_tokenize_empty_input() {
-        let input = "";
-        let expected: Vec<Token> = vec![];
+    fn test_tokenize_factorial() {
+        let input = "5! - 1";
+        let expected = vec![
+            Token::Number(5.0),
+            Token::Factorial,
+            Token::Minus,
+            Token::Number(1.0),
+        ];
         assert_eq!(tokenize(input).unwrap(), expected);
     }
 
     #[test]
-    fn test_tokenize_single_number() {
-        let input = "42";
-        let expected = vec![Token::Number(42.0)];
-        assert_eq!(tokenize(input).unwrap(), expected);
+    fn test_token_display() {
+        assert_eq!(Token::Number(2.0).to_string(), "2");
+        assert_eq!(Token::Number(0.5).to_string(), "0.5");
+        assert_eq!(Token::Plus.to_string(), "+");
+        assert_eq!(Token::UnaryMinus.to_string(), "-");
+        assert_eq!(Token::Multiply.to_string(), "*");
+        assert_eq!(Token::Power.to_string(), "^");
+        assert_eq!(Token::Factorial.to_string(), "!");
+        assert_eq!(Token::Function("sin".to_string()).to_string(), "sin");
+        assert_eq!(Token::Ans.to_string(), "ans");
     }
 
     #[test]
-    fn test_tokenize_single_operator() {
-        let input = "+";
-        let expected = vec![Token::Plus];
-        assert_eq!(tokenize(input).unwrap(), expected);
+    fn test_tokenize_all_collects_errors() {
+        let (tokens, errors) = tokenize_all("2 @ 3 # 4");
+        assert_eq!(
+            tokens,
+            vec![Token::Number(2.0), Token::Number(3.0), Token::Number(4.0)]
+        );
+        assert_eq!(
+            errors,
+            vec![
+                ParseError::InvalidTokenAt { ch: '@', pos: 2 },
+                ParseError::InvalidTokenAt { ch: '#', pos: 6 },
+            ]
+        );
     }
 
     #[test]
-    fn test_tokenize_mixed_tokens() {
-        let input = "1 + -2 * (3 / 4)";
+    fn test_tokenize_with_warnings() {
         let expected = vec![
-            Token::Number(1.0),
-            Token::Plus,
-            Token::UnaryMinus,
             Token::Number(2.0),
-            Token::Multiply,
+            Token::Minus,
+            Token::UnaryMinus,
+            Token::Number(3.0),
+        ];
+
+        let (tokens, warnings) = tokenize_with_warnings("2--3").unwrap();
+        assert_eq!(tokens, expected);
+        assert_eq!(
+            warnings,
+            vec!["Знак '-' на позиции 2 после оператора '-' прочитан как унарный минус"]
+        );
+
+        let (tokens, warnings) = tokenize_with_warnings("2 - -3").unwrap();
+        assert_eq!(tokens, expected);
+        assert_eq!(
+            warnings,
+            vec!["Знак '-' на позиции 4 после оператора '-' прочитан как унарный минус"]
+        );
+
+        let (_, warnings) = tokenize_with_warnings("2 * +3").unwrap();
+        assert_eq!(
+            warnings,
+            vec!["Знак '+' на позиции 4 после оператора '*' прочитан как унарный плюс"]
+        );
+
+        // Унарный знак в начале выражения или после скобки не неоднозначен
+        let (_, warnings) = tokenize_with_warnings("-2 - (-3)").unwrap();
+        assert!(warnings.is_empty());
+
+        assert!(tokenize_with_warnings("2 - @").is_err());
+    }
+
+    #[test]
+    fn test_tokenize_all_valid_input() {
+        let (tokens, errors) = tokenize_all("1 + 2");
+        assert_eq!(
+            tokens,
+            vec![Token::Number(1.0), Token::Plus, Token::Number(2.0)]
+        );
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_tokenize_comma() {
+        let input = "max(3, -7)";
+        let expected = vec![
+            Token::Function("max".to_string()),
             Token::LParen,
             Token::Number(3.0),
-            Token::Divide,
-            Token::Number(4.0),
+            Token::Comma,
+            Token::UnaryMinus,
+            Token::Number(7.0),
             Token::RParen,
         ];
         assert_eq!(tokenize(input).unwrap(), expected);
+
+        // При десятичной запятой аргументы разделяются точкой с запятой
+        let tokens = tokenize_with_separator("min(1,5; 2)", DecimalSeparator::Comma).unwrap();
+        let expected = vec![
+            Token::Function("min".to_string()),
+            Token::LParen,
+            Token::Number(1.5),
+            Token::Comma,
+            Token::Number(2.0),
+            Token::RParen,
+        ];
+        assert_eq!(tokens, expected);
     }
 
     #[test]
-    fn test_tokenize_negative_number() {
-        let input = "-5";
-        let expected = vec![Token::UnaryMinus, Token::Number(5.0)];
-        assert_eq!(tokenize(input).unwrap(), expected);
+    fn test_tokenize_digit_separators() {
+        assert_eq!(tokenize("1_000").unwrap(), vec![Token::Number(1000.0)]);
+        assert_eq!(
+            tokenize("1_000_000.000_5").unwrap(),
+            vec![Token::Number(1_000_000.000_5)]
+        );
+        assert_eq!(tokenize("0xFF_FF").unwrap(), vec![Token::Number(65535.0)]);
+
+        // Подчёркивание только между цифрами
+        for input in ["1__0", "5_", "1_.5", "1._5", "1_e5", "0x_FF"] {
+            assert!(
+                matches!(tokenize(input), Err(ParseError::InvalidToken(_))),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(
+            tokenize("_5"),
+            Err(ParseError::InvalidTokenAt { ch: '_', pos: 0 })
+        );
     }
 
     #[test]
-    fn test_tokenize_number_with_leading_dot() {
-        let input = ".5";
-        let expected = vec![Token::Number(0.5)];
-        assert_eq!(tokenize(input).unwrap(), expected);
+    fn test_tokenize_power() {
+        let expected = vec![Token::Number(2.0), Token::Power, Token::Number(3.0)];
+        assert_eq!(tokenize("2^3").unwrap(), expected);
+        assert_eq!(tokenize("2 ** 3").unwrap(), expected);
+
+        // С пробелом между звёздочками это два умножения
+        assert_eq!(
+            tokenize("2 * * 3").unwrap(),
+            vec![
+                Token::Number(2.0),
+                Token::Multiply,
+                Token::Multiply,
+                Token::Number(3.0),
+            ]
+        );
     }
 
     #[test]
-    fn test_tokenize_number_with_trailing_dot() {
-        let input = "5.";
-        let expected = vec![Token::Number(5.0)];
+    fn test_tokenize_bitwise() {
+        let input = "6 & 3 | 5 ^^ 1 ^ 2";
+        let expected = vec![
+            Token::Number(6.0),
+            Token::BitAnd,
+            Token::Number(3.0),
+            Token::BitOr,
+            Token::Number(5.0),
+            Token::BitXor,
+            Token::Number(1.0),
+            Token::Power,
+            Token::Number(2.0),
+        ];
         assert_eq!(tokenize(input).unwrap(), expected);
     }
 
     #[test]
-    fn test_tokenize_number_with_multiple_dots() {
-        let input = "1.2.3";
-        assert!(matches!(tokenize(input), Err(CalcError::InvalidToken(_))));
+    fn test_tokenize_percent_and_modulo() {
+        // Без операнда справа "%" означает процент
+        assert_eq!(
+            tokenize("15%").unwrap(),
+            vec![Token::Number(15.0), Token::Percent]
+        );
+        assert_eq!(
+            tokenize("(10)% * 2").unwrap(),
+            vec![
+                Token::LParen,
+                Token::Number(10.0),
+                Token::RParen,
+                Token::Percent,
+                Token::Multiply,
+                Token::Number(2.0),
+            ]
+        );
+
+        // С операндом справа — остаток от деления
+        assert_eq!(
+            tokenize("10 % 3").unwrap(),
+            vec![Token::Number(10.0), Token::Modulo, Token::Number(3.0)]
+        );
+        assert_eq!(
+            tokenize("10 %(3)").unwrap(),
+            vec![
+                Token::Number(10.0),
+                Token::Modulo,
+                Token::LParen,
+                Token::Number(3.0),
+                Token::RParen,
+            ]
+        );
     }
 
     #[test]
-    fn test_tokenize_number_with_invalid_character() {
-        let input = "1a2";
-        assert!(matches!(tokenize(input), Err(CalcError::InvalidToken(_))));
-    }
+    fn test_tokenize_implicit_multiplication() {
+        let input = "2(3)";
+        let expected = vec![
+            Token::Number(2.0),
+            Token::Multiply,
+            Token::LParen,
+            Token::Number(3.0),
+            Token::RParen,
+        ];
+        assert_eq!(tokenize(input).unwrap(), expected);
 
-    #[test]
-    fn test_tokenize_chained_operators() {
-        let input = "1 + - * /";
+        let input = "3pi";
+        let expected = vec![
+            Token::Number(3.0),
+            Token::Multiply,
+            Token::Number(std::f64::consts::PI),
+        ];
+        assert_eq!(tokenize(input).unwrap(), expected);
+
+        let input = "(1)(2)";
         let expected = vec![
+            Token::LParen,
             Token::Number(1.0),
-            Token::Plus,
-            Token::UnaryMinus,
+            Token::RParen,
             Token::Multiply,
-            Token::Divide,
+            Token::LParen,
+            Token::Number(2.0),
+            Token::RParen,
         ];
         assert_eq!(tokenize(input).unwrap(), expected);
     }
 
+    #[test]
+    fn test_tokenize_no_implicit_multiplication_between_numbers() {
+        let input = "2 3";
+        let expected = vec![Token::Number(2.0), Token::Number(3.0)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
     #[test]
     fn test_tokenize_parentheses_only() {
         let input = "()";
@@ -285,7 +1607,91 @@ mod tests_tokenize {
     #[test]
     fn test_tokenize_scientific_notation() {
         let input = "1e10";
-        assert!(matches!(tokenize(input), Err(CalcError::InvalidToken(_))));
+        let expected = vec![Token::Number(1e10)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_scientific_notation_negative_exponent() {
+        let input = "2.5e-3";
+        let expected = vec![Token::Number(2.5e-3)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+
+        let input = "2.5E+3 - 1";
+        let expected = vec![Token::Number(2.5e3), Token::Minus, Token::Number(1.0)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_scientific_notation_malformed() {
+        assert!(matches!(tokenize("1E"), Err(ParseError::InvalidToken(_))));
+        assert!(matches!(tokenize("1E-"), Err(ParseError::InvalidToken(_))));
+        assert!(matches!(
+            tokenize("2eabc"),
+            Err(ParseError::InvalidToken(_))
+        ));
+    }
+
+    #[test]
+    fn test_tokenize_number_before_constant_e() {
+        let e = Token::Number(std::f64::consts::E);
+        assert_eq!(
+            tokenize("2e").unwrap(),
+            vec![Token::Number(2.0), Token::Multiply, e.clone()]
+        );
+        assert_eq!(tokenize("2e-1").unwrap(), vec![Token::Number(0.2)]);
+        assert_eq!(
+            tokenize("2e - 1").unwrap(),
+            vec![
+                Token::Number(2.0),
+                Token::Multiply,
+                e.clone(),
+                Token::Minus,
+                Token::Number(1.0)
+            ]
+        );
+        assert_eq!(
+            tokenize("3e+pi").unwrap(),
+            vec![
+                Token::Number(3.0),
+                Token::Multiply,
+                e,
+                Token::Plus,
+                Token::Number(std::f64::consts::PI)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_hex_literal() {
+        let input = "0xFF + 1";
+        let expected = vec![Token::Number(255.0), Token::Plus, Token::Number(1.0)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+
+        let input = "0XaB";
+        let expected = vec![Token::Number(171.0)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_binary_literal() {
+        let input = "0b1010 * 2";
+        let expected = vec![Token::Number(10.0), Token::Multiply, Token::Number(2.0)];
+        assert_eq!(tokenize(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokenize_invalid_radix_literal() {
+        assert!(matches!(tokenize("0xG"), Err(ParseError::InvalidToken(_))));
+        assert!(matches!(tokenize("0x"), Err(ParseError::InvalidToken(_))));
+        assert!(matches!(
+            tokenize("0x + 1"),
+            Err(ParseError::InvalidToken(_))
+        ));
+        assert!(matches!(
+            tokenize("0b102"),
+            Err(ParseError::InvalidToken(_))
+        ));
     }
 
     #[test]
@@ -369,7 +1775,7 @@ mod tests_tokenize {
     #[test]
     fn test_tokenize_invalid_after_power() {
         let input = "2^a";
-        assert!(matches!(tokenize(input), Err(CalcError::InvalidToken(_))));
+        assert!(matches!(tokenize(input), Err(ParseError::InvalidToken(_))));
     }
 
     #[test]
@@ -421,6 +1827,163 @@ mod tests_tokenize {
     }
 }
 
+// Тесты для Lexer
+#[cfg(test)]
+mod tests_lexer {
+    use super::*;
+
+    #[test]
+    fn test_lexer_yields_tokens_lazily() {
+        let mut lexer = Lexer::new("2 + 3");
+        assert_eq!(lexer.next(), Some(Ok(Token::Number(2.0))));
+        assert_eq!(lexer.next(), Some(Ok(Token::Plus)));
+        assert_eq!(lexer.next(), Some(Ok(Token::Number(3.0))));
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn test_lexer_matches_tokenize() {
+        let inputs = [
+            "12.5 - 4.2 * (3 / 7)",
+            "-(1 + 2) * -3",
+            "2(3)pi + 0xFF - 1e-3",
+            "sqrt(16)! % +2",
+            "",
+        ];
+        for input in inputs {
+            let from_lexer: Result<Vec<Token>, ParseError> = Lexer::new(input).collect();
+            assert_eq!(from_lexer, tokenize(input), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_lexer_stops_at_error_lazily() {
+        // Токены до ошибки выдаются до того, как лексер дойдёт до неё
+        let mut lexer = Lexer::new("1 + $");
+        assert_eq!(lexer.next(), Some(Ok(Token::Number(1.0))));
+        assert_eq!(lexer.next(), Some(Ok(Token::Plus)));
+        assert_eq!(
+            lexer.next(),
+            Some(Err(ParseError::InvalidTokenAt { ch: '$', pos: 4 }))
+        );
+    }
+
+    #[test]
+    fn test_lexer_history_ref() {
+        assert_eq!(
+            tokenize("$1 + 2$12"),
+            Ok(vec![
+                Token::HistoryRef(1),
+                Token::Plus,
+                Token::Number(2.0),
+                Token::Multiply,
+                Token::HistoryRef(12),
+            ])
+        );
+        assert!(matches!(tokenize("$0"), Err(ParseError::InvalidToken(_))));
+        // "$" без номера — по-прежнему недопустимый символ
+        assert_eq!(
+            tokenize("$x"),
+            Err(ParseError::InvalidTokenAt { ch: '$', pos: 0 })
+        );
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn test_lexer_comma_separator() {
+        let tokens = tokenize_with_separator("3,14 + 1", DecimalSeparator::Comma).unwrap();
+        let expected = vec![Token::Number(3.14), Token::Plus, Token::Number(1.0)];
+        assert_eq!(tokens, expected);
+
+        let tokens = tokenize_with_separator(",5", DecimalSeparator::Comma).unwrap();
+        assert_eq!(tokens, vec![Token::Number(0.5)]);
+
+        // В режиме запятой точка недопустима
+        assert_eq!(
+            tokenize_with_separator("3.14", DecimalSeparator::Comma),
+            Err(ParseError::InvalidTokenAt { ch: '.', pos: 1 })
+        );
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn test_lexer_comma_not_decimal_by_default() {
+        // По умолчанию запятая разделяет аргументы функции, а не дробную часть
+        assert_eq!(
+            tokenize("3,14").unwrap(),
+            vec![Token::Number(3.0), Token::Comma, Token::Number(14.0)]
+        );
+        assert_eq!(
+            tokenize_with_separator("3.14", DecimalSeparator::Dot).unwrap(),
+            vec![Token::Number(3.14)]
+        );
+    }
+
+    #[test]
+    fn test_close_parens() {
+        let closed = |input: &str| {
+            let mut tokens = tokenize_spanned(input, None).unwrap();
+            close_parens(&mut tokens, input.len());
+            tokens.into_iter().map(|t| t.token).collect::<Vec<_>>()
+        };
+        assert_eq!(closed("(2+3"), tokenize("(2+3)").unwrap());
+        assert_eq!(closed("sin((1"), tokenize("sin((1))").unwrap());
+        assert_eq!(closed("(1)+2"), tokenize("(1)+2").unwrap());
+        // Лишняя закрывающая скобка остаётся ошибкой
+        assert_eq!(closed("1)+(2"), tokenize("1)+(2").unwrap());
+
+        let mut tokens = tokenize_spanned("(1", None).unwrap();
+        close_parens(&mut tokens, 2);
+        assert_eq!(tokens[2], Spanned::new(Token::RParen, 2..2));
+    }
+
+    #[test]
+    fn test_lexer_dms() {
+        let dms = |input: &str| {
+            Lexer::new(input)
+                .with_dms(true)
+                .collect::<Result<Vec<_>, _>>()
+        };
+        assert_eq!(dms("90d30m").unwrap(), vec![Token::Number(90.5)]);
+        assert_eq!(dms("1d").unwrap(), vec![Token::Number(1.0)]);
+        assert_eq!(dms("30m").unwrap(), vec![Token::Number(0.5)]);
+        assert_eq!(dms("10d30m36s").unwrap(), vec![Token::Number(10.51)]);
+        assert_eq!(
+            dms("2d + 1.5").unwrap(),
+            vec![Token::Number(2.0), Token::Plus, Token::Number(1.5)]
+        );
+        // Экспонента и функции на m не затрагиваются
+        assert_eq!(dms("2e3").unwrap(), vec![Token::Number(2000.0)]);
+        assert_eq!(
+            dms("2max(1, 2)").unwrap()[..3],
+            [
+                Token::Number(2.0),
+                Token::Multiply,
+                Token::Function("max".to_string())
+            ]
+        );
+
+        assert!(matches!(dms("90d30"), Err(ParseError::InvalidToken(_))));
+        assert!(matches!(dms("30m1d"), Err(ParseError::InvalidToken(_))));
+        // Без флага суффикс считается идентификатором
+        assert!(tokenize("90d30m").is_err());
+    }
+
+    #[test]
+    fn test_lexer_with_vars() {
+        let vars = HashMap::from([("x".to_string(), 1.0)]);
+        let tokens: Vec<Token> = Lexer::with_vars("2x", &vars)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let expected = vec![
+            Token::Number(2.0),
+            Token::Multiply,
+            Token::Variable("x".to_string()),
+        ];
+        assert_eq!(tokens, expected);
+    }
+}
+
 // Тесты для precedence
 #[cfg(test)]
 mod tests_precedence {
@@ -444,16 +2007,84 @@ mod tests_precedence {
     fn test_precedence_plus_minus() {
         let plus = Token::Plus;
         let minus = Token::Minus;
-        assert_eq!(plus.precedence(), 2);
-        assert_eq!(minus.precedence(), 2);
+        assert_eq!(plus.precedence(), 6);
+        assert_eq!(minus.precedence(), 6);
     }
 
     #[test]
     fn test_precedence_multiply_divide() {
         let multiply = Token::Multiply;
         let divide = Token::Divide;
-        assert_eq!(multiply.precedence(), 3);
-        assert_eq!(divide.precedence(), 3);
+        let modulo = Token::Modulo;
+        assert_eq!(multiply.precedence(), 7);
+        assert_eq!(divide.precedence(), 7);
+        assert_eq!(modulo.precedence(), 7);
+    }
+
+    #[test]
+    fn test_precedence_bitwise() {
+        assert!(Token::BitOr.precedence() > Token::LParen.precedence());
+        assert!(Token::BitOr.precedence() < Token::BitXor.precedence());
+        assert!(Token::BitXor.precedence() < Token::BitAnd.precedence());
+        assert!(Token::BitAnd.precedence() < Token::Plus.precedence());
+    }
+
+    #[test]
+    fn test_precedence_comparison_operators() {
+        for op in [
+            Token::Less,
+            Token::LessEqual,
+            Token::Greater,
+            Token::GreaterEqual,
+            Token::Equal,
+            Token::NotEqual,
+        ] {
+            assert!(op.is_binary_operator());
+            assert!(op.precedence() > Token::LParen.precedence());
+            assert!(op.precedence() < Token::BitOr.precedence());
+        }
+    }
+
+    #[test]
+    fn test_is_binary_operator() {
+        assert!(Token::Plus.is_binary_operator());
+        assert!(Token::Power.is_binary_operator());
+        assert!(!Token::UnaryMinus.is_binary_operator());
+        assert!(!Token::Factorial.is_binary_operator());
+        assert!(!Token::Number(1.0).is_binary_operator());
+    }
+
+    #[test]
+    fn test_binds_tighter_than() {
+        // Разные приоритеты
+        assert!(Token::Multiply.binds_tighter_than(&Token::Plus));
+        assert!(!Token::Plus.binds_tighter_than(&Token::Multiply));
+        assert!(Token::Power.binds_tighter_than(&Token::Multiply));
+        assert!(Token::UnaryMinus.binds_tighter_than(&Token::Minus));
+        assert!(!Token::UnaryMinus.binds_tighter_than(&Token::Power));
+        assert!(Token::BitAnd.binds_tighter_than(&Token::BitOr));
+        assert!(Token::BitOr.binds_tighter_than(&Token::Equal));
+
+        // Равный приоритет: левая ассоциативность
+        assert!(Token::Minus.binds_tighter_than(&Token::Minus));
+        assert!(Token::Plus.binds_tighter_than(&Token::Minus));
+        assert!(Token::Divide.binds_tighter_than(&Token::Multiply));
+        assert!(Token::Less.binds_tighter_than(&Token::Equal));
+
+        // Равный приоритет: правая ассоциативность степени
+        assert!(!Token::Power.binds_tighter_than(&Token::Power));
+
+        // Скобка в стеке не выталкивается операторами
+        assert!(!Token::LParen.binds_tighter_than(&Token::Less));
+    }
+
+    #[test]
+    fn test_right_associative() {
+        assert!(Token::Power.is_right_associative());
+        assert!(!Token::Plus.is_right_associative());
+        assert!(!Token::Minus.is_right_associative());
+        assert!(!Token::Divide.is_right_associative());
+        assert!(!Token::BitXor.is_right_associative());
     }
 
     #[test]
@@ -492,6 +2123,13 @@ mod tests_precedence {
 
         assert!(power.precedence() > multiply.precedence());
         assert!(power.precedence() > plus.precedence());
+
+        let function = Token::Function("sin".to_string());
+        assert!(function.precedence() > power.precedence());
+
+        let factorial = Token::Factorial;
+        assert!(factorial.precedence() > power.precedence());
+        assert!(factorial.precedence() > function.precedence());
     }
 }
 
@@ -546,10 +2184,10 @@ mod tests_validate_parens {
             Token::Plus,
             Token::Number(2.0),
         ];
-        assert!(matches!(
+        assert_eq!(
             validate_parens(&tokens),
-            Err(CalcError::UnmatchedParens)
-        ));
+            Err(ParseError::UnmatchedParensAt(0))
+        );
     }
 
     #[test]
@@ -560,10 +2198,10 @@ mod tests_validate_parens {
             Token::Number(2.0),
             Token::RParen,
         ];
-        assert!(matches!(
+        assert_eq!(
             validate_parens(&tokens),
-            Err(CalcError::UnmatchedParens)
-        ));
+            Err(ParseError::UnmatchedParensAt(3))
+        );
     }
 
     #[test]
@@ -576,10 +2214,10 @@ mod tests_validate_parens {
             Token::Number(2.0),
             Token::RParen,
         ];
-        assert!(matches!(
+        assert_eq!(
             validate_parens(&tokens),
-            Err(CalcError::UnmatchedParens)
-        ));
+            Err(ParseError::UnmatchedParensAt(0))
+        );
     }
 
     #[test]
@@ -591,10 +2229,10 @@ mod tests_validate_parens {
             Token::RParen,
             Token::RParen,
         ];
-        assert!(matches!(
+        assert_eq!(
             validate_parens(&tokens),
-            Err(CalcError::UnmatchedParens)
-        ));
+            Err(ParseError::UnmatchedParensAt(3))
+        );
     }
 
     #[test]
@@ -606,10 +2244,10 @@ mod tests_validate_parens {
             Token::Number(2.0),
             Token::LParen,
         ];
-        assert!(matches!(
+        assert_eq!(
             validate_parens(&tokens),
-            Err(CalcError::UnmatchedParens)
-        ));
+            Err(ParseError::UnmatchedParensAt(0))
+        );
     }
 
     #[test]
@@ -636,10 +2274,10 @@ mod tests_validate_parens {
             Token::Number(3.0),
             Token::RParen,
         ];
-        assert!(matches!(
+        assert_eq!(
             validate_parens(&tokens),
-            Err(CalcError::UnmatchedParens)
-        ));
+            Err(ParseError::UnmatchedParensAt(0))
+        );
     }
 
     #[test]
@@ -659,3 +2297,138 @@ mod tests_validate_parens {
         assert_eq!(validate_parens(&tokens), Ok(()));
     }
 }
+
+// Тесты для validate_depth
+#[cfg(test)]
+mod tests_validate_depth {
+    use super::*;
+
+    #[test]
+    fn test_validate_depth() {
+        let nested = |n: usize| tokenize(&format!("{}1{}", "(".repeat(n), ")".repeat(n))).unwrap();
+
+        assert_eq!(validate_depth(&nested(3), 3), Ok(()));
+        assert_eq!(
+            validate_depth(&nested(4), 3),
+            Err(ParseError::NestingTooDeep(3))
+        );
+        assert_eq!(
+            validate_depth(&nested(300), MAX_NESTING_DEPTH),
+            Err(ParseError::NestingTooDeep(MAX_NESTING_DEPTH))
+        );
+    }
+
+    #[test]
+    fn test_validate_depth_sequential() {
+        // Соседние скобки не увеличивают глубину
+        let tokens = tokenize("(1) + (2) * ((3) - (4))").unwrap();
+        assert_eq!(validate_depth(&tokens, 2), Ok(()));
+    }
+}
+
+// Тесты для validate_syntax
+#[cfg(test)]
+mod tests_validate_syntax {
+    use super::*;
+
+    #[test]
+    fn test_validate_syntax_valid() {
+        let tokens = vec![Token::Number(1.0), Token::Plus, Token::Number(2.0)];
+        assert_eq!(validate_syntax(&tokens), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_syntax_adjacent_numbers() {
+        // 1 2
+        let tokens = vec![Token::Number(1.0), Token::Number(2.0)];
+        assert_eq!(
+            validate_syntax(&tokens),
+            Err(ParseError::InvalidExpression(
+                "Пропущен оператор между операндами '1' и '2'".to_string()
+            ))
+        );
+
+        // 3 4 5
+        let tokens = vec![Token::Number(3.0), Token::Number(4.0), Token::Number(5.0)];
+        assert!(matches!(
+            validate_syntax(&tokens),
+            Err(ParseError::InvalidExpression(_))
+        ));
+
+        // 1 + 2 3
+        let tokens = vec![
+            Token::Number(1.0),
+            Token::Plus,
+            Token::Number(2.0),
+            Token::Number(3.0),
+        ];
+        assert_eq!(
+            validate_syntax(&tokens),
+            Err(ParseError::InvalidExpression(
+                "Пропущен оператор между операндами '2' и '3'".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_validate_syntax_spanned_position() {
+        // Позиция — смещение второго операнда в строке, а не номер токена
+        for (input, pos) in [("1  2", 3), ("1 + 2 3", 6), ("2 +  3 4", 7)] {
+            let tokens = tokenize_spanned(input, None).unwrap();
+            assert_eq!(
+                validate_syntax_spanned(&tokens),
+                Err(ParseError::InvalidExpression(format!(
+                    "Пропущен оператор между операндами в позиции {}",
+                    pos
+                ))),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_syntax_leading_operator() {
+        // * 3
+        let tokens = vec![Token::Multiply, Token::Number(3.0)];
+        assert_eq!(
+            validate_syntax(&tokens),
+            Err(ParseError::InvalidExpression(
+                "Выражение начинается с оператора '*'".to_string()
+            ))
+        );
+
+        // + 3
+        let tokens = vec![Token::Plus, Token::Number(3.0)];
+        assert!(matches!(
+            validate_syntax(&tokens),
+            Err(ParseError::InvalidExpression(_))
+        ));
+
+        // -3
+        let tokens = vec![Token::UnaryMinus, Token::Number(3.0)];
+        assert_eq!(validate_syntax(&tokens), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_syntax_percent() {
+        let tokens = vec![Token::Number(15.0), Token::Percent];
+        assert_eq!(validate_syntax(&tokens), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_syntax_trailing_operator() {
+        // 3 +
+        let tokens = vec![Token::Number(3.0), Token::Plus];
+        assert_eq!(
+            validate_syntax(&tokens),
+            Err(ParseError::InvalidExpression(
+                "Выражение заканчивается оператором '+'".to_string()
+            ))
+        );
+
+        // 3!
+        let tokens = vec![Token::Number(3.0), Token::Factorial];
+        assert_eq!(validate_syntax(&tokens), Ok(()));
+    }
+}
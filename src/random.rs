@@ -0,0 +1,74 @@
+// Генератор псевдослучайных чисел xorshift64* для функции rand
+
+use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+thread_local! {
+    // Состояние генератора; ноль означает, что начальное значение ещё не задано
+    static STATE: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Задаёт начальное значение генератора, чтобы последовательность rand()
+/// повторялась между запусками. Без вызова берётся текущее время.
+/// Состояние хранится отдельно для каждого потока: значение действует
+/// только на rand() в потоке, который вызвал set_seed.
+pub fn set_seed(seed: u64) {
+    STATE.with(|state| state.set(nonzero(seed)));
+}
+
+/// Возвращает следующее псевдослучайное число из [0, 1).
+pub fn next_f64() -> f64 {
+    STATE.with(|state| {
+        let mut x = state.get();
+        if x == 0 {
+            x = nonzero(time_seed());
+        }
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        // Старшие 53 бита помещаются в мантиссу f64 без округления
+        (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
+
+// Из нулевого состояния xorshift не выходит, поэтому ноль заменяется константой
+fn nonzero(seed: u64) -> u64 {
+    if seed == 0 {
+        0x9E37_79B9_7F4A_7C15
+    } else {
+        seed
+    }
+}
+
+fn time_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_f64, set_seed};
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        set_seed(42);
+        let first: Vec<f64> = (0..5).map(|_| next_f64()).collect();
+        set_seed(42);
+        let second: Vec<f64> = (0..5).map(|_| next_f64()).collect();
+        assert_eq!(first, second);
+
+        set_seed(43);
+        assert_ne!(next_f64(), first[0]);
+    }
+
+    #[test]
+    fn test_range() {
+        set_seed(0);
+        for _ in 0..1000 {
+            let x = next_f64();
+            assert!((0.0..1.0).contains(&x));
+        }
+    }
+}
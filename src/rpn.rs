@@ -1,47 +1,169 @@
 use std::collections::VecDeque;
 
-use crate::error::CalcError;
-use crate::parser::Token;
+use crate::error::{EvalError, ParseError, SpannedError};
+use crate::output;
+use crate::parser::{MAX_NESTING_DEPTH, PrecedenceTable, Spanned, Token, function_arity};
+use crate::random;
 
 /// Алгоритм сортировочной станции (Shunting-yard)
-pub fn to_rpn(tokens: Vec<Token>) -> Result<VecDeque<Token>, CalcError> {
-    let mut output: VecDeque<Token> = VecDeque::with_capacity(tokens.len());
-    let mut operators: Vec<Token> = Vec::new();
-
-    for token in tokens {
-        match token {
-            Token::Number(_) => output.push_back(token),
-            Token::LParen | Token::UnaryMinus | Token::Power => operators.push(token),
+pub fn to_rpn(tokens: Vec<Token>) -> Result<VecDeque<Token>, ParseError> {
+    to_rpn_with(tokens, &PrecedenceTable::default())
+}
+
+/// Алгоритм сортировочной станции с заданной таблицей приоритетов.
+/// Пример: при `power` ниже `multiplicative` "2 * 3 ^ 2" → 2 3 * 2 ^
+pub fn to_rpn_with(
+    tokens: Vec<Token>,
+    table: &PrecedenceTable,
+) -> Result<VecDeque<Token>, ParseError> {
+    let tokens = tokens.into_iter().map(|t| Spanned::new(t, 0..0)).collect();
+    let rpn = shunting_yard(tokens, table, &mut RpnBuffers::default())?;
+    Ok(rpn.into_iter().map(|t| t.token).collect())
+}
+
+/// Алгоритм сортировочной станции с сохранением положения токенов.
+pub fn to_rpn_spanned(tokens: Vec<Spanned>) -> Result<VecDeque<Spanned>, ParseError> {
+    to_rpn_spanned_in(tokens, &mut RpnBuffers::default())
+}
+
+/// Рабочие буферы алгоритма сортировочной станции. Их можно передавать
+/// в `to_rpn_spanned_in` повторно, чтобы не выделять память на каждое выражение.
+#[derive(Debug, Default)]
+pub struct RpnBuffers {
+    operators: Vec<Spanned>,
+    // Число аргументов внутри каждой открытой скобки
+    arg_counts: Vec<usize>,
+}
+
+/// Алгоритм сортировочной станции с сохранением положения токенов,
+/// использующий переданные буферы.
+pub fn to_rpn_spanned_in(
+    tokens: Vec<Spanned>,
+    buffers: &mut RpnBuffers,
+) -> Result<VecDeque<Spanned>, ParseError> {
+    shunting_yard(tokens, &PrecedenceTable::default(), buffers)
+}
+
+fn shunting_yard(
+    tokens: Vec<Spanned>,
+    table: &PrecedenceTable,
+    buffers: &mut RpnBuffers,
+) -> Result<VecDeque<Spanned>, ParseError> {
+    let mut output: VecDeque<Spanned> = VecDeque::with_capacity(tokens.len());
+    let RpnBuffers {
+        operators,
+        arg_counts,
+    } = buffers;
+    // После ошибки в прошлом вызове в буферах могли остаться элементы
+    operators.clear();
+    arg_counts.clear();
+
+    // Предыдущий токен — открывающая скобка: "()" означает вызов без аргументов
+    let mut after_lparen = false;
+    for spanned in tokens {
+        let is_lparen = spanned.token == Token::LParen;
+        match spanned.token {
+            // Постфиксный оператор применяется к уже выведенному операнду
+            Token::Number(_)
+            | Token::Ans
+            | Token::HistoryRef(_)
+            | Token::Variable(_)
+            | Token::Factorial
+            | Token::Percent => output.push_back(spanned),
+            Token::LParen => {
+                arg_counts.push(1);
+                operators.push(spanned);
+            }
+            Token::UnaryMinus | Token::UnaryPlus | Token::Function(_) => operators.push(spanned),
             Token::RParen => {
                 while let Some(top) = operators.pop() {
-                    match top {
+                    match top.token {
                         Token::LParen => break,
                         _ => output.push_back(top),
                     }
 
                     // Проверяем на наличие непарных скобок
                     if operators.is_empty() {
-                        return Err(CalcError::UnmatchedParens);
+                        return Err(ParseError::UnmatchedParens);
+                    }
+                }
+
+                // Скобка закрывает аргументы функции: "sin(...)", "max(..., ...)"
+                let args = arg_counts.pop().unwrap_or(1);
+                let args = if after_lparen { 0 } else { args };
+                if let Some(Token::Function(name)) = operators.last().map(|t| &t.token) {
+                    // "rand()" равносильно "rand(0, 1)"
+                    if args == 0 && name == "rand" {
+                        let span = operators.last().unwrap().span.clone();
+                        for value in [0.0, 1.0] {
+                            output.push_back(Spanned {
+                                token: Token::Number(value),
+                                span: span.clone(),
+                            });
+                        }
+                    } else if args != function_arity(name) {
+                        return Err(ParseError::InvalidExpression(format!(
+                            "Функция '{}' принимает аргументов: {}, передано: {}",
+                            name,
+                            function_arity(name),
+                            args
+                        )));
+                    }
+                    output.push_back(operators.pop().unwrap());
+                } else if args > 1 {
+                    return Err(ParseError::InvalidExpression(
+                        "Запятая вне вызова функции".to_string(),
+                    ));
+                }
+            }
+            Token::Comma => {
+                // Выводим операторы текущего аргумента, оставляя открывающую скобку
+                while let Some(top) = operators.last() {
+                    if top.token == Token::LParen {
+                        break;
                     }
+                    output.push_back(operators.pop().unwrap());
                 }
+
+                let Some(args) = arg_counts.last_mut() else {
+                    return Err(ParseError::InvalidExpression(
+                        "Запятая вне вызова функции".to_string(),
+                    ));
+                };
+                *args += 1;
             }
-            Token::Plus | Token::Minus | Token::Multiply | Token::Divide => {
+            Token::Plus
+            | Token::Minus
+            | Token::Multiply
+            | Token::Divide
+            | Token::Modulo
+            | Token::Power
+            | Token::BitAnd
+            | Token::BitOr
+            | Token::BitXor
+            | Token::Less
+            | Token::LessEqual
+            | Token::Greater
+            | Token::GreaterEqual
+            | Token::Equal
+            | Token::NotEqual => {
                 while let Some(top) = operators.last() {
-                    if top.precedence() >= token.precedence() {
+                    if table.binds_tighter(&top.token, &spanned.token) {
                         output.push_back(operators.pop().unwrap());
                     } else {
                         break;
                     }
                 }
-                operators.push(token);
-            } // _ => return Err(CalcError::InvalidToken(format!("{:?}", token))),
+                operators.push(spanned);
+            } // _ => return Err(ParseError::InvalidToken(format!("{:?}", token))),
         }
+        after_lparen = is_lparen;
     }
 
     // Переносим оставшиеся операторы в выходную очередь
     while let Some(op) = operators.pop() {
-        if op == Token::LParen {
-            return Err(CalcError::UnmatchedParens);
+        if op.token == Token::LParen {
+            return Err(ParseError::UnmatchedParens);
         }
         output.push_back(op);
     }
@@ -49,66 +171,749 @@ pub fn to_rpn(tokens: Vec<Token>) -> Result<VecDeque<Token>, CalcError> {
     Ok(output)
 }
 
-/// Вычисляет результат ОПЗ.
-pub fn eval_rpn(mut rpn: VecDeque<Token>) -> Result<f64, CalcError> {
+// Единицы измерения углов для тригонометрических функций
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AngleMode {
+    #[default]
+    Radians,
+    Degrees,
+}
+
+impl AngleMode {
+    // Переводит угол в радианы
+    fn to_radians(self, x: f64) -> f64 {
+        match self {
+            AngleMode::Radians => x,
+            AngleMode::Degrees => x.to_radians(),
+        }
+    }
+
+    // Переводит угол из радиан в текущие единицы
+    fn radians_to_mode(self, x: f64) -> f64 {
+        match self {
+            AngleMode::Radians => x,
+            AngleMode::Degrees => x.to_degrees(),
+        }
+    }
+}
+
+// Поведение при делении на ноль
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DivByZeroPolicy {
+    // Ошибка EvalError::DivideByZero
+    #[default]
+    Error,
+    // Результат по IEEE 754: 1/0 → inf, -1/0 → -inf
+    Infinity,
+}
+
+/// Настройки вычисления выражения.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalOptions {
+    pub angle_mode: AngleMode,
+    pub divide_by_zero: DivByZeroPolicy,
+    // Наибольшая допустимая глубина вложенности скобок
+    pub max_depth: usize,
+    // Наибольшее число операций при вычислении; None — без ограничения
+    pub max_steps: Option<usize>,
+    // Точное вычисление в обыкновенных дробях, если в выражении только целые
+    // числа и операции + - * / ^: "1/3*3" → ровно 1. Иначе вычисление в f64
+    pub exact: bool,
+    // Делитель с |b| < zero_epsilon считается нулём; 0.0 — только точный ноль
+    pub zero_epsilon: f64,
+    // Процент при сложении и вычитании берётся от левого операнда, как в
+    // электронных таблицах: "100 + 20%" → 120. Иначе "20%" всегда равно 0.2
+    pub percent_of: bool,
+    // Углы в градусах, минутах и секундах: "90d30m" → 90.5
+    pub dms: bool,
+    // Незакрытые скобки закрываются в конце выражения: "(2 + 3" → 5
+    pub lenient: bool,
+    // Результат каждой операции должен быть целым: "7 / 2" — ошибка
+    pub integers_only: bool,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        EvalOptions {
+            angle_mode: AngleMode::default(),
+            divide_by_zero: DivByZeroPolicy::default(),
+            max_depth: MAX_NESTING_DEPTH,
+            max_steps: None,
+            exact: false,
+            zero_epsilon: 0.0,
+            percent_of: false,
+            dms: false,
+            lenient: false,
+            integers_only: false,
+        }
+    }
+}
+
+/// Вычисляет результат ОПЗ. Очередь не изменяется, так что одну и ту же
+/// ОПЗ можно вычислять многократно.
+pub fn eval_rpn(rpn: &VecDeque<Token>) -> Result<f64, EvalError> {
+    eval_rpn_with(rpn, &EvalOptions::default())
+}
+
+/// Вычисляет результат ОПЗ с заданными настройками.
+pub fn eval_rpn_with(rpn: &VecDeque<Token>, options: &EvalOptions) -> Result<f64, EvalError> {
     let mut stack: Vec<f64> = Vec::new();
+    let mut steps = 0;
 
-    while let Some(token) = rpn.pop_front() {
-        match token {
-            Token::Number(num) => stack.push(num),
-            Token::UnaryMinus => {
-                let Some(x) = stack.pop() else {
-                    return Err(CalcError::InvalidExpression(
-                        "Унарный минус требует одного операнда".to_string(),
-                    ));
-                };
+    let mut tokens = rpn.iter().peekable();
+    while let Some(token) = tokens.next() {
+        count_step(token, &mut steps, options)?;
+        apply_before(token, tokens.peek().copied(), &mut stack, options)?;
+    }
+
+    let result = refine_exact(rpn.iter(), finish(stack)?, options);
+    check_integer(result, options)?;
+    Ok(result)
+}
+
+/// Вычисляет результат ОПЗ и записывает состояние стека после каждого токена.
+/// Пример: "2 3 4 * +" → (14, ["2 → [2]", "3 → [2, 3]", "4 → [2, 3, 4]",
+/// "* → [2, 12]", "+ → [14]"])
+pub fn eval_rpn_traced(
+    rpn: &VecDeque<Token>,
+    options: &EvalOptions,
+) -> Result<(f64, Vec<String>), EvalError> {
+    let mut stack: Vec<f64> = Vec::new();
+    let mut steps = Vec::with_capacity(rpn.len());
+    let mut count = 0;
+
+    let mut tokens = rpn.iter().peekable();
+    while let Some(token) = tokens.next() {
+        count_step(token, &mut count, options)?;
+        apply_before(token, tokens.peek().copied(), &mut stack, options)?;
+        let state: Vec<String> = stack.iter().map(|&x| output::format_number(x)).collect();
+        steps.push(format!("{} → [{}]", token, state.join(", ")));
+    }
+
+    let result = refine_exact(rpn.iter(), finish(stack)?, options);
+    check_integer(result, options)?;
+    Ok((result, steps))
+}
+
+/// Вычисляет результат ОПЗ, указывая в ошибке положение токена, на котором
+/// она произошла. Ошибки итогового стека относятся ко всему выражению.
+pub fn eval_rpn_spanned(
+    rpn: &VecDeque<Spanned>,
+    options: &EvalOptions,
+) -> Result<f64, SpannedError> {
+    let mut stack: Vec<f64> = Vec::new();
+    let mut end = 0;
+
+    let mut steps = 0;
+
+    let mut tokens = rpn.iter().peekable();
+    while let Some(Spanned { token, span }) = tokens.next() {
+        end = end.max(span.end);
+        let next = tokens.peek().map(|t| &t.token);
+        count_step(token, &mut steps, options)
+            .and_then(|_| apply_before(token, next, &mut stack, options))
+            .map_err(|error| SpannedError {
+                error: error.into(),
+                span: span.clone(),
+            })?;
+    }
+
+    let result = finish(stack)
+        .map(|result| refine_exact(rpn.iter().map(|t| &t.token), result, options))
+        .and_then(|result| check_integer(result, options).map(|_| result))
+        .map_err(|error| SpannedError {
+            error: error.into(),
+            span: 0..end,
+        })?;
+    Ok(result)
+}
+
+// В точном режиме заменяет результат в f64 результатом в дробях, если его
+// удаётся вычислить. Ошибки и лимиты уже проверены вычислением в f64.
+fn refine_exact<'a>(
+    rpn: impl Iterator<Item = &'a Token>,
+    result: f64,
+    options: &EvalOptions,
+) -> f64 {
+    if !options.exact {
+        return result;
+    }
+    eval_exact(rpn).map_or(result, Ratio::to_f64)
+}
+
+// Обыкновенная дробь num/den: знаменатель положителен, дробь несократима
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Ratio {
+    num: i64,
+    den: i64,
+}
+
+impl Ratio {
+    // Сокращает дробь; None при нулевом знаменателе или переполнении
+    fn new(num: i64, den: i64) -> Option<Ratio> {
+        if den == 0 {
+            return None;
+        }
+        let (mut x, mut y) = (num.unsigned_abs(), den.unsigned_abs());
+        while y != 0 {
+            (x, y) = (y, x % y);
+        }
+        let gcd = i64::try_from(x).ok()?.max(1);
+        let sign = if den < 0 { -1 } else { 1 };
+        Some(Ratio {
+            num: (num / gcd).checked_mul(sign)?,
+            den: (den / gcd).checked_mul(sign)?,
+        })
+    }
+
+    fn add(self, other: Ratio) -> Option<Ratio> {
+        Ratio::new(
+            self.num
+                .checked_mul(other.den)?
+                .checked_add(other.num.checked_mul(self.den)?)?,
+            self.den.checked_mul(other.den)?,
+        )
+    }
+
+    fn neg(self) -> Option<Ratio> {
+        Some(Ratio {
+            num: self.num.checked_neg()?,
+            den: self.den,
+        })
+    }
+
+    fn mul(self, other: Ratio) -> Option<Ratio> {
+        Ratio::new(
+            self.num.checked_mul(other.num)?,
+            self.den.checked_mul(other.den)?,
+        )
+    }
+
+    fn recip(self) -> Option<Ratio> {
+        Ratio::new(self.den, self.num)
+    }
 
-                stack.push(-x);
+    // Степень с целым показателем; дробный показатель не поддерживается
+    fn pow(self, exp: Ratio) -> Option<Ratio> {
+        if exp.den != 1 {
+            return None;
+        }
+        let n = u32::try_from(exp.num.unsigned_abs()).ok()?;
+        let power = Ratio::new(self.num.checked_pow(n)?, self.den.checked_pow(n)?)?;
+        if exp.num < 0 {
+            power.recip()
+        } else {
+            Some(power)
+        }
+    }
+
+    fn to_f64(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+}
+
+// Вычисляет ОПЗ в обыкновенных дробях. None, если встретились нецелое число,
+// функция или операция, отличная от + - * / ^, либо произошло переполнение
+fn eval_exact<'a>(rpn: impl Iterator<Item = &'a Token>) -> Option<Ratio> {
+    let mut stack: Vec<Ratio> = Vec::new();
+
+    for token in rpn {
+        let value = match token {
+            Token::Number(num) => Ratio::new(to_integer(*num)?, 1)?,
+            Token::UnaryPlus => stack.pop()?,
+            Token::UnaryMinus => stack.pop()?.neg()?,
+            Token::Plus | Token::Minus | Token::Multiply | Token::Divide | Token::Power => {
+                let b = stack.pop()?;
+                let a = stack.pop()?;
+                match token {
+                    Token::Plus => a.add(b)?,
+                    Token::Minus => a.add(b.neg()?)?,
+                    Token::Multiply => a.mul(b)?,
+                    Token::Divide => a.mul(b.recip()?)?,
+                    _ => a.pow(b)?,
+                }
             }
-            _ => {
-                let (Some(b), Some(a)) = (stack.pop(), stack.pop()) else {
-                    return Err(CalcError::InvalidExpression(format!(
-                        "Недостаточно операндов для операции '{:?}'",
-                        token
+            _ => return None,
+        };
+        stack.push(value);
+    }
+
+    match stack[..] {
+        [result] => Some(result),
+        _ => None,
+    }
+}
+
+// Считает операции (всё, кроме операндов) и проверяет лимит EvalOptions::max_steps
+fn count_step(token: &Token, steps: &mut usize, options: &EvalOptions) -> Result<(), EvalError> {
+    if matches!(
+        token,
+        Token::Number(_) | Token::Ans | Token::HistoryRef(_) | Token::Variable(_)
+    ) {
+        return Ok(());
+    }
+
+    *steps += 1;
+    match options.max_steps {
+        Some(max) if *steps > max => Err(EvalError::StepLimitExceeded(max)),
+        _ => Ok(()),
+    }
+}
+
+// Применяет токен ОПЗ с учётом следующего токена. В режиме percent_of
+// процент перед "+" или "-" берётся от левого операнда: "100 20 % +" → 100 + 20
+fn apply_before(
+    token: &Token,
+    next: Option<&Token>,
+    stack: &mut Vec<f64>,
+    options: &EvalOptions,
+) -> Result<(), EvalError> {
+    if options.percent_of
+        && *token == Token::Percent
+        && matches!(next, Some(Token::Plus | Token::Minus))
+        && let [.., base, percent] = stack[..]
+    {
+        stack.pop();
+        stack.push(percent_of(base, percent, options)?);
+        return Ok(());
+    }
+    apply(token, stack, options)
+}
+
+// Процент от левого операнда в режиме percent_of: 100 и 20 → 20
+pub(crate) fn percent_of(base: f64, percent: f64, options: &EvalOptions) -> Result<f64, EvalError> {
+    let value = base * percent / 100.0;
+    check_integer(value, options)?;
+    Ok(value)
+}
+
+// Применяет токен ОПЗ к стеку вычислений
+pub(crate) fn apply(
+    token: &Token,
+    stack: &mut Vec<f64>,
+    options: &EvalOptions,
+) -> Result<(), EvalError> {
+    let mode = options.angle_mode;
+    let div_by_zero_is_error = options.divide_by_zero == DivByZeroPolicy::Error;
+    let is_zero = |b: f64| b == 0.0 || b.abs() < options.zero_epsilon;
+
+    match token {
+        Token::Number(num) => stack.push(*num),
+        Token::Ans => {
+            return Err(EvalError::InvalidExpression(
+                "Нет предыдущего результата для 'ans'".to_string(),
+            ));
+        }
+        Token::HistoryRef(n) => {
+            return Err(EvalError::InvalidExpression(format!(
+                "Нет результата с номером {} для '${}'",
+                n, n
+            )));
+        }
+        Token::Variable(name) => {
+            return Err(EvalError::UnknownVariable(name.clone()));
+        }
+        Token::UnaryMinus => {
+            let Some(x) = stack.pop() else {
+                return Err(EvalError::InvalidExpression(
+                    "Унарный минус требует одного операнда".to_string(),
+                ));
+            };
+
+            stack.push(-x);
+        }
+        Token::UnaryPlus => {
+            if stack.is_empty() {
+                return Err(EvalError::InvalidExpression(
+                    "Унарный плюс требует одного операнда".to_string(),
+                ));
+            }
+        }
+        Token::Factorial => {
+            let Some(x) = stack.pop() else {
+                return Err(EvalError::InvalidExpression(
+                    "Факториал требует одного операнда".to_string(),
+                ));
+            };
+
+            stack.push(factorial(x)?);
+        }
+        Token::Percent => {
+            let Some(x) = stack.pop() else {
+                return Err(EvalError::InvalidExpression(
+                    "Процент требует одного операнда".to_string(),
+                ));
+            };
+
+            stack.push(x / 100.0);
+        }
+        Token::Function(name) if function_arity(name) == 2 => {
+            let (Some(b), Some(a)) = (stack.pop(), stack.pop()) else {
+                return Err(EvalError::InvalidExpression(format!(
+                    "Функция '{}' требует двух аргументов",
+                    name
+                )));
+            };
+
+            stack.push(match name.as_str() {
+                "min" => a.min(b),
+                "max" => a.max(b),
+                "root" => root(a, b)?,
+                "gcd" | "lcm" => gcd_lcm(name, a, b)?,
+                // Число из [a, b); при a > b диапазон пуст
+                "rand" if a > b => {
+                    return Err(EvalError::DomainError(format!(
+                        "rand({}, {}): нижняя граница больше верхней",
+                        a, b
                     )));
-                };
+                }
+                "rand" => a + (b - a) * random::next_f64(),
+                // Угол точки (x, y) = (b, a) в текущих единицах
+                "atan2" => mode.radians_to_mode(a.atan2(b)),
+                _ => {
+                    return Err(EvalError::InvalidToken(format!(
+                        "Неизвестная функция: '{}'",
+                        name
+                    )));
+                }
+            });
+        }
+        Token::Function(name) => {
+            let Some(x) = stack.pop() else {
+                return Err(EvalError::InvalidExpression(format!(
+                    "Функция '{}' требует одного аргумента",
+                    name
+                )));
+            };
 
-                stack.push(match token {
-                    Token::Power => a.powf(b),
-                    Token::Plus => a + b,
-                    Token::Minus => a - b,
-                    Token::Multiply => a * b,
-                    Token::Divide => {
-                        if b == 0.0 {
-                            return Err(CalcError::DivideByZero);
-                        }
-                        a / b
+            stack.push(match name.as_str() {
+                "sin" => mode.to_radians(x).sin(),
+                "cos" => mode.to_radians(x).cos(),
+                "tan" => mode.to_radians(x).tan(),
+                "cot" | "sec" | "csc" => reciprocal_trig(name, x, mode)?,
+                // Обратные функции возвращают угол в текущих единицах
+                "asin" | "acos" => {
+                    if !(-1.0..=1.0).contains(&x) {
+                        return Err(EvalError::DomainError(format!(
+                            "Функция '{}' определена только на отрезке [-1, 1], получено {}",
+                            name, x
+                        )));
                     }
-                    _ => {
-                        return Err(CalcError::InvalidExpression(format!(
-                            "Неподдерживаемый токен: {:?}",
-                            token
+                    mode.radians_to_mode(if name == "asin" { x.asin() } else { x.acos() })
+                }
+                "atan" => mode.radians_to_mode(x.atan()),
+                // Гиперболические функции не зависят от единиц измерения углов
+                "sinh" => x.sinh(),
+                "cosh" => x.cosh(),
+                "tanh" => x.tanh(),
+                "sqrt" => {
+                    if x < 0.0 {
+                        return Err(EvalError::DomainError(format!(
+                            "Квадратный корень из отрицательного числа {}",
+                            x
                         )));
                     }
-                });
-            }
+                    x.sqrt()
+                }
+                "exp" => x.exp(),
+                "ln" | "log" => {
+                    if x <= 0.0 {
+                        return Err(EvalError::DomainError(format!(
+                            "Логарифм определён только для положительных чисел, получено {}",
+                            x
+                        )));
+                    }
+                    if name == "ln" { x.ln() } else { x.log10() }
+                }
+                "abs" => x.abs(),
+                // signum(0.0) равен 1.0, поэтому ноль обрабатывается отдельно
+                "sign" if x == 0.0 => 0.0,
+                "sign" => x.signum(),
+                "trunc" => x.trunc(),
+                "cbrt" => x.cbrt(),
+                "floor" => x.floor(),
+                "ceil" => x.ceil(),
+                // Половины округляются от нуля: round(2.5) → 3, round(-2.5) → -3
+                "round" => x.round(),
+                _ => {
+                    return Err(EvalError::InvalidToken(format!(
+                        "Неизвестная функция: '{}'",
+                        name
+                    )));
+                }
+            });
+        }
+        _ => {
+            let (Some(b), Some(a)) = (stack.pop(), stack.pop()) else {
+                return Err(EvalError::InvalidExpression(format!(
+                    "Недостаточно операндов для операции '{}'",
+                    token
+                )));
+            };
+
+            stack.push(match token {
+                // Целый неотрицательный показатель в пределах i32 возводится через
+                // powi: 10^3 → ровно 1000. Для отрицательных powi теряет точность
+                // (10^-23 ≠ 1e-23), поэтому они вычисляются через powf
+                Token::Power => match to_integer(b).and_then(|n| i32::try_from(n).ok()) {
+                    Some(n) if n >= 0 => a.powi(n),
+                    _ => a.powf(b),
+                },
+                Token::Plus => a + b,
+                Token::Minus => a - b,
+                Token::Multiply => a * b,
+                Token::Divide => {
+                    if is_zero(b) && div_by_zero_is_error {
+                        return Err(EvalError::DivideByZero);
+                    }
+                    a / b
+                }
+                Token::Modulo => {
+                    if is_zero(b) {
+                        return Err(EvalError::DivideByZero);
+                    }
+                    a % b
+                }
+                Token::BitAnd | Token::BitOr | Token::BitXor => bitwise(token, a, b)?,
+                // Сравнение даёт 1 (истина) или 0 (ложь)
+                Token::Less => f64::from(a < b),
+                Token::LessEqual => f64::from(a <= b),
+                Token::Greater => f64::from(a > b),
+                Token::GreaterEqual => f64::from(a >= b),
+                Token::Equal => f64::from(a == b),
+                Token::NotEqual => f64::from(a != b),
+                _ => {
+                    return Err(EvalError::InvalidExpression(format!(
+                        "Неподдерживаемый токен: '{}'",
+                        token
+                    )));
+                }
+            });
+        }
+    }
+
+    // Переполнение и неопределённость не должны молча распространяться дальше.
+    // При политике DivByZeroPolicy::Infinity бесконечность — допустимый результат.
+    match stack.last() {
+        Some(x) if x.is_nan() => Err(EvalError::NotFinite(
+            "результат не определён (NaN)".to_string(),
+        )),
+        Some(x) if x.is_infinite() && div_by_zero_is_error => {
+            Err(EvalError::NotFinite("переполнение".to_string()))
         }
+        // Числа из выражения проверяются только как результаты операций
+        Some(&x) if !matches!(token, Token::Number(_)) => check_integer(x, options),
+        _ => Ok(()),
+    }
+}
+
+// В режиме integers_only запрещает дробный результат операции или всего
+// выражения
+fn check_integer(x: f64, options: &EvalOptions) -> Result<(), EvalError> {
+    if options.integers_only && x.is_finite() && x.fract() != 0.0 {
+        return Err(EvalError::DomainError(format!(
+            "нецелый результат {} в целочисленном режиме",
+            output::format_number(x)
+        )));
     }
+    Ok(())
+}
 
+// Извлекает итоговый результат из стека
+fn finish(mut stack: Vec<f64>) -> Result<f64, EvalError> {
     match (stack.pop(), stack.is_empty()) {
         (Some(result), true) => Ok(result),
-        (Some(_), _) => Err(CalcError::InvalidExpression(
+        (Some(_), _) => Err(EvalError::InvalidExpression(
             "В стеке остались лишние числа".to_string(),
         )),
-        (_, _) => Err(CalcError::InvalidExpression(
+        (_, _) => Err(EvalError::InvalidExpression(
             "Стек пуст после вычислений".to_string(),
         )),
     }
 }
 
+// Применяет побитовую операцию к целым операндам
+fn bitwise(op: &Token, a: f64, b: f64) -> Result<f64, EvalError> {
+    let to_int = |x: f64| {
+        to_integer(x).ok_or_else(|| {
+            EvalError::DomainError(format!(
+                "Операция '{}' определена только для целых чисел, получено {}",
+                op, x
+            ))
+        })
+    };
+
+    let (a, b) = (to_int(a)?, to_int(b)?);
+    let result = match op {
+        Token::BitAnd => a & b,
+        Token::BitOr => a | b,
+        _ => a ^ b,
+    };
+    Ok(result as f64)
+}
+
+// Преобразует число в i64, если оно целое и помещается в i64
+fn to_integer(x: f64) -> Option<i64> {
+    (x.fract() == 0.0 && x.abs() <= i64::MAX as f64).then_some(x as i64)
+}
+
+// Вычисляет НОД или НОК целых чисел алгоритмом Евклида
+fn gcd_lcm(name: &str, a: f64, b: f64) -> Result<f64, EvalError> {
+    let to_int = |x: f64| {
+        to_integer(x).ok_or_else(|| {
+            EvalError::DomainError(format!(
+                "Функция '{}' определена только для целых чисел, получено {}",
+                name, x
+            ))
+        })
+    };
+
+    let (a, b) = (to_int(a)?.unsigned_abs(), to_int(b)?.unsigned_abs());
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    let gcd = x;
+
+    if name == "gcd" {
+        return Ok(gcd as f64);
+    }
+    if gcd == 0 {
+        return Ok(0.0);
+    }
+    // НОК может не поместиться в u64
+    Ok((a / gcd) as f64 * b as f64)
+}
+
+// Относительная точность, с которой аргумент считается полюсом: из-за
+// округления числа pi sin(pi) и cos(pi / 2) дают не ноль, а ~1e-16
+const TRIG_POLE_TOLERANCE: f64 = 1e-12;
+
+// Котангенс, секанс и косеканс как величины, обратные tan, cos и sin.
+// Ошибкой считается только полюс: точный ноль знаменателя или аргумент,
+// кратный pi (для sec — pi / 2 + k * pi). Малые аргументы вроде 1e-16 полюсом
+// не являются: csc(1e-16) → 1e16.
+fn reciprocal_trig(name: &str, x: f64, mode: AngleMode) -> Result<f64, EvalError> {
+    let radians = mode.to_radians(x);
+    let denominator = match name {
+        "cot" => radians.tan(),
+        "sec" => radians.cos(),
+        _ => radians.sin(),
+    };
+    let turns = radians / std::f64::consts::PI;
+    let offset = if name == "sec" { turns - 0.5 } else { turns };
+    let near_pole = (offset - offset.round()).abs() <= TRIG_POLE_TOLERANCE * turns.abs();
+    if denominator == 0.0 || near_pole {
+        return Err(EvalError::DomainError(format!(
+            "Функция '{}' не определена для аргумента {}",
+            name, x
+        )));
+    }
+    Ok(1.0 / denominator)
+}
+
+// Вычисляет корень степени n. Корень нечётной степени из отрицательного
+// числа определён: root(-8, 3) → -2, а чётной — нет.
+fn root(x: f64, n: f64) -> Result<f64, EvalError> {
+    if n == 0.0 {
+        return Err(EvalError::DomainError(
+            "Степень корня не может быть равна 0".to_string(),
+        ));
+    }
+
+    if x >= 0.0 {
+        return Ok(x.powf(1.0 / n));
+    }
+
+    if n.fract() == 0.0 && n % 2.0 != 0.0 {
+        Ok(-(-x).powf(1.0 / n))
+    } else {
+        Err(EvalError::DomainError(format!(
+            "Корень степени {} из отрицательного числа {}",
+            n, x
+        )))
+    }
+}
+
+/// Вычисляет факториал неотрицательного целого числа.
+fn factorial(x: f64) -> Result<f64, EvalError> {
+    if x < 0.0 || x.fract() != 0.0 {
+        return Err(EvalError::DomainError(format!(
+            "Факториал определён только для неотрицательных целых чисел, получено {}",
+            x
+        )));
+    }
+
+    // 171! уже не помещается в f64
+    if x > 170.0 {
+        return Ok(f64::INFINITY);
+    }
+
+    Ok((1..=x as u64).map(|n| n as f64).product())
+}
+
+/// Восстанавливает из ОПЗ инфиксную запись с явными скобками вокруг каждой операции.
+/// Недостающие операнды обозначаются как "?".
+/// Пример: 2 3 4 * + → "(2 + (3 * 4))"
+pub fn rpn_to_infix(rpn: &VecDeque<Token>) -> String {
+    let mut stack: Vec<String> = Vec::new();
+    let pop = |stack: &mut Vec<String>| stack.pop().unwrap_or_else(|| "?".to_string());
+
+    for token in rpn {
+        let expr = match token {
+            Token::UnaryMinus | Token::UnaryPlus => format!("({}{})", token, pop(&mut stack)),
+            Token::Factorial | Token::Percent => format!("({}{})", pop(&mut stack), token),
+            Token::Function(name) if function_arity(name) == 2 => {
+                let right = pop(&mut stack);
+                let left = pop(&mut stack);
+                format!("{}({}, {})", name, left, right)
+            }
+            Token::Function(name) => {
+                let arg = pop(&mut stack);
+                // Аргумент в скобках не оборачиваем повторно: "sin(1 + 2)"
+                if is_parenthesized(&arg) {
+                    format!("{}{}", name, arg)
+                } else {
+                    format!("{}({})", name, arg)
+                }
+            }
+            _ if token.is_binary_operator() => {
+                let right = pop(&mut stack);
+                let left = pop(&mut stack);
+                format!("({} {} {})", left, token, right)
+            }
+            _ => token.to_string(),
+        };
+        stack.push(expr);
+    }
+
+    stack.join(" ")
+}
+
+// Проверяет, что строка целиком заключена в одну пару скобок
+fn is_parenthesized(s: &str) -> bool {
+    if !s.starts_with('(') {
+        return false;
+    }
+
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return i == s.len() - 1;
+        }
+    }
+    false
+}
+
 #[cfg(test)]
 mod tests_to_rpn {
     use super::*;
+    use crate::error::CalcError;
     use crate::parser::Token;
 
     #[test]
@@ -158,7 +963,7 @@ mod tests_to_rpn {
             Token::Multiply,
             Token::Number(3.0),
         ];
-        assert!(matches!(to_rpn(tokens), Err(CalcError::UnmatchedParens)));
+        assert!(matches!(to_rpn(tokens), Err(ParseError::UnmatchedParens)));
 
         // 1 + 2 )
         let tokens = vec![
@@ -167,7 +972,7 @@ mod tests_to_rpn {
             Token::Number(2.0),
             Token::RParen,
         ];
-        assert!(matches!(to_rpn(tokens), Err(CalcError::UnmatchedParens)));
+        assert!(matches!(to_rpn(tokens), Err(ParseError::UnmatchedParens)));
     }
 
     #[test]
@@ -210,99 +1015,232 @@ mod tests_to_rpn {
     }
 
     #[test]
-    fn test_associativity() {
-        // 1 - 2 - 3
-        let tokens = vec![
-            Token::Number(1.0),
-            Token::Minus,
-            Token::Number(2.0),
-            Token::Minus,
-            Token::Number(3.0),
-        ];
-        // 1 2 - 3 -
-        let expected = vec![
-            Token::Number(1.0),
-            Token::Number(2.0),
-            Token::Minus,
-            Token::Number(3.0),
-            Token::Minus,
-        ];
-        assert_eq!(to_rpn(tokens).unwrap(), expected);
-    }
+    fn test_custom_precedence_table() {
+        // 2 * 3 ^ 2
+        let tokens = || {
+            vec![
+                Token::Number(2.0),
+                Token::Multiply,
+                Token::Number(3.0),
+                Token::Power,
+                Token::Number(2.0),
+            ]
+        };
 
-    #[test]
-    fn test_negative_number() {
-        // - 5
-        let tokens = vec![Token::UnaryMinus, Token::Number(5.0)];
-        let expected = vec![Token::Number(5.0), Token::UnaryMinus];
-        assert_eq!(to_rpn(tokens).unwrap(), expected);
+        // По умолчанию: 2 3 2 ^ *
+        let default = PrecedenceTable::default();
+        assert_eq!(
+            to_rpn_with(tokens(), &default).unwrap(),
+            to_rpn(tokens()).unwrap()
+        );
+        assert_eq!(
+            to_rpn_with(tokens(), &default).unwrap(),
+            vec![
+                Token::Number(2.0),
+                Token::Number(3.0),
+                Token::Number(2.0),
+                Token::Power,
+                Token::Multiply,
+            ]
+        );
+
+        // Степень слабее умножения: 2 3 * 2 ^
+        let loose_power = PrecedenceTable {
+            power: 4,
+            ..PrecedenceTable::default()
+        };
+        assert_eq!(
+            to_rpn_with(tokens(), &loose_power).unwrap(),
+            vec![
+                Token::Number(2.0),
+                Token::Number(3.0),
+                Token::Multiply,
+                Token::Number(2.0),
+                Token::Power,
+            ]
+        );
     }
 
     #[test]
-    fn test_negative_number_in_expression() {
-        // 2 - (-3)
+    fn test_modulo_precedence() {
+        // 1 + 10 % 3
         let tokens = vec![
-            Token::Number(2.0),
-            Token::Minus,
-            Token::LParen,
-            Token::UnaryMinus,
+            Token::Number(1.0),
+            Token::Plus,
+            Token::Number(10.0),
+            Token::Modulo,
             Token::Number(3.0),
-            Token::RParen,
         ];
-        // 2 3 - -
+        // 1 10 3 % +
         let expected = vec![
-            Token::Number(2.0),
+            Token::Number(1.0),
+            Token::Number(10.0),
             Token::Number(3.0),
-            Token::UnaryMinus,
-            Token::Minus,
+            Token::Modulo,
+            Token::Plus,
         ];
         assert_eq!(to_rpn(tokens).unwrap(), expected);
     }
 
     #[test]
-    fn test_unary_minus_complex() {
-        // -(1 + 2) * -3
+    fn test_function() {
+        // sin(1 + 2) * 3
         let tokens = vec![
-            Token::UnaryMinus,
+            Token::Function("sin".to_string()),
             Token::LParen,
             Token::Number(1.0),
             Token::Plus,
             Token::Number(2.0),
             Token::RParen,
             Token::Multiply,
-            Token::UnaryMinus,
             Token::Number(3.0),
         ];
-        // 1 2 + - 3 - *
+        // 1 2 + sin 3 *
         let expected = vec![
             Token::Number(1.0),
             Token::Number(2.0),
             Token::Plus,
-            Token::UnaryMinus,
+            Token::Function("sin".to_string()),
             Token::Number(3.0),
-            Token::UnaryMinus,
             Token::Multiply,
         ];
         assert_eq!(to_rpn(tokens).unwrap(), expected);
     }
 
     #[test]
-    fn test_power_simple() {
-        // 2^3
-        let tokens = vec![Token::Number(2.0), Token::Power, Token::Number(3.0)];
-        // 2 3 ^
-        let expected = vec![Token::Number(2.0), Token::Number(3.0), Token::Power];
-        assert_eq!(to_rpn(tokens).unwrap(), expected);
-    }
-
-    #[test]
-    fn test_power_priority() {
-        // 2^3*4
+    fn test_factorial() {
+        // 2^3!
         let tokens = vec![
             Token::Number(2.0),
             Token::Power,
             Token::Number(3.0),
-            Token::Multiply,
+            Token::Factorial,
+        ];
+        // 2 3 ! ^
+        let expected = vec![
+            Token::Number(2.0),
+            Token::Number(3.0),
+            Token::Factorial,
+            Token::Power,
+        ];
+        assert_eq!(to_rpn(tokens).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_spans_preserved() {
+        // 1 / 0
+        let tokens = vec![
+            Spanned::new(Token::Number(1.0), 0..1),
+            Spanned::new(Token::Divide, 2..3),
+            Spanned::new(Token::Number(0.0), 4..5),
+        ];
+        let expected = vec![
+            Spanned::new(Token::Number(1.0), 0..1),
+            Spanned::new(Token::Number(0.0), 4..5),
+            Spanned::new(Token::Divide, 2..3),
+        ];
+        let rpn = to_rpn_spanned(tokens).unwrap();
+        assert_eq!(rpn, expected);
+
+        let err = eval_rpn_spanned(&rpn, &EvalOptions::default()).unwrap_err();
+        assert_eq!(err.error, CalcError::Eval(EvalError::DivideByZero));
+        assert_eq!(err.span, 2..3);
+    }
+
+    #[test]
+    fn test_associativity() {
+        // 1 - 2 - 3
+        let tokens = vec![
+            Token::Number(1.0),
+            Token::Minus,
+            Token::Number(2.0),
+            Token::Minus,
+            Token::Number(3.0),
+        ];
+        // 1 2 - 3 -
+        let expected = vec![
+            Token::Number(1.0),
+            Token::Number(2.0),
+            Token::Minus,
+            Token::Number(3.0),
+            Token::Minus,
+        ];
+        assert_eq!(to_rpn(tokens).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_negative_number() {
+        // - 5
+        let tokens = vec![Token::UnaryMinus, Token::Number(5.0)];
+        let expected = vec![Token::Number(5.0), Token::UnaryMinus];
+        assert_eq!(to_rpn(tokens).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_negative_number_in_expression() {
+        // 2 - (-3)
+        let tokens = vec![
+            Token::Number(2.0),
+            Token::Minus,
+            Token::LParen,
+            Token::UnaryMinus,
+            Token::Number(3.0),
+            Token::RParen,
+        ];
+        // 2 3 - -
+        let expected = vec![
+            Token::Number(2.0),
+            Token::Number(3.0),
+            Token::UnaryMinus,
+            Token::Minus,
+        ];
+        assert_eq!(to_rpn(tokens).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_unary_minus_complex() {
+        // -(1 + 2) * -3
+        let tokens = vec![
+            Token::UnaryMinus,
+            Token::LParen,
+            Token::Number(1.0),
+            Token::Plus,
+            Token::Number(2.0),
+            Token::RParen,
+            Token::Multiply,
+            Token::UnaryMinus,
+            Token::Number(3.0),
+        ];
+        // 1 2 + - 3 - *
+        let expected = vec![
+            Token::Number(1.0),
+            Token::Number(2.0),
+            Token::Plus,
+            Token::UnaryMinus,
+            Token::Number(3.0),
+            Token::UnaryMinus,
+            Token::Multiply,
+        ];
+        assert_eq!(to_rpn(tokens).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_power_simple() {
+        // 2^3
+        let tokens = vec![Token::Number(2.0), Token::Power, Token::Number(3.0)];
+        // 2 3 ^
+        let expected = vec![Token::Number(2.0), Token::Number(3.0), Token::Power];
+        assert_eq!(to_rpn(tokens).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_power_priority() {
+        // 2^3*4
+        let tokens = vec![
+            Token::Number(2.0),
+            Token::Power,
+            Token::Number(3.0),
+            Token::Multiply,
             Token::Number(4.0),
         ];
 
@@ -363,6 +1301,50 @@ mod tests_to_rpn {
         assert_eq!(to_rpn(tokens).unwrap(), expected);
     }
 
+    #[test]
+    fn test_power_then_multiply() {
+        // 2^3*4 → (2^3)*4
+        let tokens = vec![
+            Token::Number(2.0),
+            Token::Power,
+            Token::Number(3.0),
+            Token::Multiply,
+            Token::Number(4.0),
+        ];
+
+        // 2 3 ^ 4 *
+        let expected = vec![
+            Token::Number(2.0),
+            Token::Number(3.0),
+            Token::Power,
+            Token::Number(4.0),
+            Token::Multiply,
+        ];
+        assert_eq!(to_rpn(tokens).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_multiply_then_power() {
+        // 4*2^3 → 4*(2^3)
+        let tokens = vec![
+            Token::Number(4.0),
+            Token::Multiply,
+            Token::Number(2.0),
+            Token::Power,
+            Token::Number(3.0),
+        ];
+
+        // 4 2 3 ^ *
+        let expected = vec![
+            Token::Number(4.0),
+            Token::Number(2.0),
+            Token::Number(3.0),
+            Token::Power,
+            Token::Multiply,
+        ];
+        assert_eq!(to_rpn(tokens).unwrap(), expected);
+    }
+
     #[test]
     fn test_unary_minus_power() {
         // -2^3, интерпретируется как -(2^3)
@@ -383,19 +1365,134 @@ mod tests_to_rpn {
     }
 }
 
+#[cfg(test)]
+mod tests_to_rpn_functions {
+    use super::*;
+    use crate::parser::Token;
+
+    #[test]
+    fn test_two_argument_function() {
+        // max(1 + 2, 3) → 1 2 + 3 max
+        let tokens = vec![
+            Token::Function("max".to_string()),
+            Token::LParen,
+            Token::Number(1.0),
+            Token::Plus,
+            Token::Number(2.0),
+            Token::Comma,
+            Token::Number(3.0),
+            Token::RParen,
+        ];
+        let expected = vec![
+            Token::Number(1.0),
+            Token::Number(2.0),
+            Token::Plus,
+            Token::Number(3.0),
+            Token::Function("max".to_string()),
+        ];
+        assert_eq!(to_rpn(tokens).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_wrong_arity() {
+        // min(3, 7, 9) → Ошибка
+        let tokens = vec![
+            Token::Function("min".to_string()),
+            Token::LParen,
+            Token::Number(3.0),
+            Token::Comma,
+            Token::Number(7.0),
+            Token::Comma,
+            Token::Number(9.0),
+            Token::RParen,
+        ];
+        assert!(matches!(
+            to_rpn(tokens),
+            Err(ParseError::InvalidExpression(_))
+        ));
+
+        // sin(1, 2) → Ошибка
+        let tokens = vec![
+            Token::Function("sin".to_string()),
+            Token::LParen,
+            Token::Number(1.0),
+            Token::Comma,
+            Token::Number(2.0),
+            Token::RParen,
+        ];
+        assert!(matches!(
+            to_rpn(tokens),
+            Err(ParseError::InvalidExpression(_))
+        ));
+    }
+
+    #[test]
+    fn test_comma_outside_function() {
+        // (1, 2) → Ошибка
+        let tokens = vec![
+            Token::LParen,
+            Token::Number(1.0),
+            Token::Comma,
+            Token::Number(2.0),
+            Token::RParen,
+        ];
+        assert!(matches!(
+            to_rpn(tokens),
+            Err(ParseError::InvalidExpression(_))
+        ));
+    }
+}
+
 #[cfg(test)]
 mod tests_eval_rpn {
     use super::*;
+    use crate::testing::approx_eq;
+
+    const EPS: f64 = 1e-12;
     use crate::parser::Token;
     use std::collections::VecDeque;
 
+    #[test]
+    fn test_eval_rpn_traced() {
+        // 2 + 3 * 4 → 2 3 4 * +
+        let rpn = to_rpn(crate::parser::tokenize("2 + 3 * 4").unwrap()).unwrap();
+        let (result, steps) = eval_rpn_traced(&rpn, &EvalOptions::default()).unwrap();
+        assert_eq!(result, 14.0);
+        assert_eq!(
+            steps,
+            [
+                "2 → [2]",
+                "3 → [2, 3]",
+                "4 → [2, 3, 4]",
+                "* → [2, 12]",
+                "+ → [14]"
+            ]
+        );
+
+        let rpn = to_rpn(crate::parser::tokenize("-0.5 / 0").unwrap()).unwrap();
+        assert_eq!(
+            eval_rpn_traced(&rpn, &EvalOptions::default()),
+            Err(EvalError::DivideByZero)
+        );
+
+        // Настройки учитываются так же, как в eval_rpn_with
+        let percent_of = EvalOptions {
+            percent_of: true,
+            ..EvalOptions::default()
+        };
+        let rpn = to_rpn(crate::parser::tokenize("100 + 20%").unwrap()).unwrap();
+        let (result, steps) = eval_rpn_traced(&rpn, &percent_of).unwrap();
+        assert_eq!(result, 120.0);
+        assert_eq!(steps[2], "% → [100, 20]");
+    }
+
     #[test]
     fn test_simple_expression() {
         // Проверка простого сложения: 2 + 3
         let tokens: VecDeque<Token> = vec![Token::Number(2.0), Token::Number(3.0), Token::Plus]
             .into_iter()
             .collect();
-        assert_eq!(eval_rpn(tokens).unwrap(), 5.0);
+        assert_eq!(eval_rpn(&tokens).unwrap(), 5.0);
     }
 
     #[test]
@@ -412,7 +1509,7 @@ mod tests_eval_rpn {
         ]
         .into_iter()
         .collect();
-        assert_eq!(eval_rpn(tokens).unwrap(), 10.7);
+        assert!(approx_eq(eval_rpn(&tokens).unwrap(), 10.7, EPS));
     }
 
     #[test]
@@ -421,7 +1518,22 @@ mod tests_eval_rpn {
         let tokens: VecDeque<Token> = vec![Token::Number(5.0), Token::UnaryMinus]
             .into_iter()
             .collect();
-        assert_eq!(eval_rpn(tokens).unwrap(), -5.0);
+        assert_eq!(eval_rpn(&tokens).unwrap(), -5.0);
+    }
+
+    #[test]
+    fn test_unary_plus() {
+        // +5
+        let tokens: VecDeque<Token> = vec![Token::Number(5.0), Token::UnaryPlus]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 5.0);
+
+        let tokens: VecDeque<Token> = vec![Token::UnaryPlus].into_iter().collect();
+        assert!(matches!(
+            eval_rpn(&tokens),
+            Err(EvalError::InvalidExpression(_))
+        ));
     }
 
     #[test]
@@ -435,7 +1547,7 @@ mod tests_eval_rpn {
         ]
         .into_iter()
         .collect();
-        assert_eq!(eval_rpn(tokens).unwrap(), 5.0);
+        assert_eq!(eval_rpn(&tokens).unwrap(), 5.0);
     }
 
     #[test]
@@ -450,7 +1562,7 @@ mod tests_eval_rpn {
         ]
         .into_iter()
         .collect();
-        assert_eq!(eval_rpn(tokens).unwrap(), 7.0);
+        assert_eq!(eval_rpn(&tokens).unwrap(), 7.0);
 
         // Проверка приоритета операторов: 1 * 2 + 3
         let tokens: VecDeque<Token> = vec![
@@ -462,7 +1574,7 @@ mod tests_eval_rpn {
         ]
         .into_iter()
         .collect();
-        assert_eq!(eval_rpn(tokens).unwrap(), 5.0);
+        assert_eq!(eval_rpn(&tokens).unwrap(), 5.0);
     }
 
     #[test]
@@ -477,7 +1589,7 @@ mod tests_eval_rpn {
         ]
         .into_iter()
         .collect();
-        assert_eq!(eval_rpn(tokens).unwrap(), -4.0);
+        assert_eq!(eval_rpn(&tokens).unwrap(), -4.0);
     }
 
     #[test]
@@ -486,64 +1598,737 @@ mod tests_eval_rpn {
         let tokens: VecDeque<Token> = vec![Token::Number(1.0), Token::Number(0.0), Token::Divide]
             .into_iter()
             .collect();
-        assert!(matches!(eval_rpn(tokens), Err(CalcError::DivideByZero)));
+        assert!(matches!(eval_rpn(&tokens), Err(EvalError::DivideByZero)));
     }
 
     #[test]
-    fn test_invalid_expression() {
-        // Проверка некорректного выражения: недостаточно операндов
-        let tokens: VecDeque<Token> = vec![Token::Number(1.0), Token::Plus].into_iter().collect();
-        assert!(matches!(
-            eval_rpn(tokens),
-            Err(CalcError::InvalidExpression(_))
-        ));
-
-        // Проверка некорректного выражения: некорректное расположение операторов
-        let tokens: VecDeque<Token> = vec![Token::Plus, Token::Number(1.0), Token::Number(2.0)]
+    fn test_modulo() {
+        // 10 % 3 → 1.0
+        let tokens = vec![Token::Number(10.0), Token::Number(3.0), Token::Modulo]
             .into_iter()
             .collect();
-        assert!(matches!(
-            eval_rpn(tokens),
-            Err(CalcError::InvalidExpression(_))
-        ));
+        assert_eq!(eval_rpn(&tokens).unwrap(), 1.0);
     }
 
     #[test]
-    fn test_invalid_token() {
-        // Проверка некорректного токена: скобка в выражении
-        let tokens: VecDeque<Token> = vec![Token::Number(1.0), Token::Number(2.0), Token::LParen]
+    fn test_modulo_by_zero() {
+        // 5 % 0 → Ошибка
+        let tokens: VecDeque<Token> = vec![Token::Number(5.0), Token::Number(0.0), Token::Modulo]
             .into_iter()
             .collect();
-        assert!(matches!(
-            eval_rpn(tokens),
-            Err(CalcError::InvalidExpression(_))
-        ));
+        assert!(matches!(eval_rpn(&tokens), Err(EvalError::DivideByZero)));
     }
 
     #[test]
-    fn test_power_simple() {
-        // 2^3 → 8.0
-        let tokens = vec![Token::Number(2.0), Token::Number(3.0), Token::Power]
+    fn test_trig_functions() {
+        // sin(0) → 0.0
+        let tokens = vec![Token::Number(0.0), Token::Function("sin".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 0.0);
+
+        // cos(0) → 1.0
+        let tokens = vec![Token::Number(0.0), Token::Function("cos".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 1.0);
+
+        // tan(0) → 0.0
+        let tokens = vec![Token::Number(0.0), Token::Function("tan".to_string())]
             .into_iter()
             .collect();
-        assert_eq!(eval_rpn(tokens).unwrap(), 8.0);
+        assert_eq!(eval_rpn(&tokens).unwrap(), 0.0);
     }
 
     #[test]
-    fn test_power_negative_exponent() {
-        // 2^-3 → 0.125
-        let tokens = vec![Token::Number(2.0), Token::Number(-3.0), Token::Power]
+    fn test_trig_angle_mode() {
+        let degrees = EvalOptions {
+            angle_mode: AngleMode::Degrees,
+            ..EvalOptions::default()
+        };
+
+        // sin(90) в градусах → 1.0
+        let tokens = vec![Token::Number(90.0), Token::Function("sin".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn_with(&tokens, &degrees).unwrap(), 1.0);
+
+        // sin(90) в радианах → 0.894
+        let tokens = vec![Token::Number(90.0), Token::Function("sin".to_string())]
+            .into_iter()
+            .collect();
+        let result = eval_rpn_with(&tokens, &EvalOptions::default()).unwrap();
+        assert!(approx_eq(result, 0.894, 1e-3));
+
+        // cos(180) в градусах → -1.0
+        let tokens = vec![Token::Number(180.0), Token::Function("cos".to_string())]
             .into_iter()
             .collect();
-        assert_eq!(eval_rpn(tokens).unwrap(), 0.125);
+        assert_eq!(eval_rpn_with(&tokens, &degrees).unwrap(), -1.0);
     }
 
     #[test]
-    fn test_power_zero_base() {
-        // 0^-2 → Ошибка (деление на ноль)
-        let tokens = vec![Token::Number(0.0), Token::Number(-2.0), Token::Power]
+    fn test_sqrt() {
+        // sqrt(16) → 4.0
+        let tokens = vec![Token::Number(16.0), Token::Function("sqrt".to_string())]
             .into_iter()
             .collect();
-        assert!(matches!(eval_rpn(tokens).unwrap(), f64::INFINITY));
+        assert_eq!(eval_rpn(&tokens).unwrap(), 4.0);
+
+        // sqrt(-4) → Ошибка
+        let tokens: VecDeque<Token> =
+            vec![Token::Number(-4.0), Token::Function("sqrt".to_string())]
+                .into_iter()
+                .collect();
+        assert!(matches!(eval_rpn(&tokens), Err(EvalError::DomainError(_))));
+    }
+
+    #[test]
+    fn test_factorial() {
+        // 0! → 1.0
+        let tokens = vec![Token::Number(0.0), Token::Factorial]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 1.0);
+
+        // 5! → 120.0
+        let tokens = vec![Token::Number(5.0), Token::Factorial]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 120.0);
+    }
+
+    #[test]
+    fn test_percent() {
+        // 15% → 0.15
+        let tokens = vec![Token::Number(15.0), Token::Percent]
+            .into_iter()
+            .collect();
+        assert!(approx_eq(eval_rpn(&tokens).unwrap(), 0.15, EPS));
+
+        // 200 15% * → 30.0
+        let tokens = vec![
+            Token::Number(200.0),
+            Token::Number(15.0),
+            Token::Percent,
+            Token::Multiply,
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 30.0);
+    }
+
+    #[test]
+    fn test_percent_of() {
+        let percent_of = EvalOptions {
+            percent_of: true,
+            ..EvalOptions::default()
+        };
+        let rpn = |input: &str| to_rpn(crate::parser::tokenize(input).unwrap()).unwrap();
+
+        // 100 + 20%: по умолчанию 100 + 0.2, в режиме percent_of 100 + 20
+        assert_eq!(eval_rpn(&rpn("100 + 20%")).unwrap(), 100.2);
+        assert_eq!(
+            eval_rpn_with(&rpn("100 + 20%"), &percent_of).unwrap(),
+            120.0
+        );
+        assert_eq!(eval_rpn_with(&rpn("100 - 20%"), &percent_of).unwrap(), 80.0);
+        assert_eq!(
+            eval_rpn_with(&rpn("(50 + 50) + 10% * 2"), &percent_of).unwrap(),
+            100.2
+        );
+
+        // При умножении и без левого операнда процент остаётся долей
+        assert_eq!(eval_rpn_with(&rpn("200 * 15%"), &percent_of).unwrap(), 30.0);
+        assert_eq!(eval_rpn_with(&rpn("20%"), &percent_of).unwrap(), 0.2);
+    }
+
+    #[test]
+    fn test_factorial_domain_error() {
+        // 2.5! → Ошибка
+        let tokens: VecDeque<Token> = vec![Token::Number(2.5), Token::Factorial]
+            .into_iter()
+            .collect();
+        assert!(matches!(eval_rpn(&tokens), Err(EvalError::DomainError(_))));
+
+        // (-3)! → Ошибка
+        let tokens: VecDeque<Token> = vec![Token::Number(-3.0), Token::Factorial]
+            .into_iter()
+            .collect();
+        assert!(matches!(eval_rpn(&tokens), Err(EvalError::DomainError(_))));
+    }
+
+    #[test]
+    fn test_logarithms() {
+        // ln(1) → 0.0
+        let tokens = vec![Token::Number(1.0), Token::Function("ln".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 0.0);
+
+        // log(1000) → 3.0
+        let tokens = vec![Token::Number(1000.0), Token::Function("log".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 3.0);
+
+        // ln(-1) → Ошибка
+        let tokens: VecDeque<Token> = vec![Token::Number(-1.0), Token::Function("ln".to_string())]
+            .into_iter()
+            .collect();
+        assert!(matches!(eval_rpn(&tokens), Err(EvalError::DomainError(_))));
+
+        // log(0) → Ошибка
+        let tokens: VecDeque<Token> = vec![Token::Number(0.0), Token::Function("log".to_string())]
+            .into_iter()
+            .collect();
+        assert!(matches!(eval_rpn(&tokens), Err(EvalError::DomainError(_))));
+    }
+
+    #[test]
+    fn test_unknown_function() {
+        let tokens: VecDeque<Token> = vec![Token::Number(1.0), Token::Function("foo".to_string())]
+            .into_iter()
+            .collect();
+        assert!(matches!(eval_rpn(&tokens), Err(EvalError::InvalidToken(_))));
+    }
+
+    #[test]
+    fn test_invalid_expression() {
+        // Проверка некорректного выражения: недостаточно операндов
+        let tokens: VecDeque<Token> = vec![Token::Number(1.0), Token::Plus].into_iter().collect();
+        assert!(matches!(
+            eval_rpn(&tokens),
+            Err(EvalError::InvalidExpression(_))
+        ));
+
+        // Проверка некорректного выражения: некорректное расположение операторов
+        let tokens: VecDeque<Token> = vec![Token::Plus, Token::Number(1.0), Token::Number(2.0)]
+            .into_iter()
+            .collect();
+        assert!(matches!(
+            eval_rpn(&tokens),
+            Err(EvalError::InvalidExpression(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_token() {
+        // Проверка некорректного токена: скобка в выражении
+        let tokens: VecDeque<Token> = vec![Token::Number(1.0), Token::Number(2.0), Token::LParen]
+            .into_iter()
+            .collect();
+        assert!(matches!(
+            eval_rpn(&tokens),
+            Err(EvalError::InvalidExpression(_))
+        ));
+    }
+
+    #[test]
+    fn test_power_simple() {
+        // 2^3 → 8.0
+        let tokens = vec![Token::Number(2.0), Token::Number(3.0), Token::Power]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 8.0);
+    }
+
+    #[test]
+    fn test_power_negative_exponent() {
+        // 2^-3 → 0.125
+        let tokens = vec![Token::Number(2.0), Token::Number(-3.0), Token::Power]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 0.125);
+
+        // 10^-23 == 1e-23: отрицательный показатель не теряет точность
+        let tokens = vec![Token::Number(10.0), Token::Number(-23.0), Token::Power]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 1e-23);
+
+        // (-2)^-3 → -0.125
+        let tokens = vec![Token::Number(-2.0), Token::Number(-3.0), Token::Power]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), -0.125);
+    }
+
+    #[test]
+    fn test_power_integer_exponent() {
+        // 10^3 → ровно 1000.0
+        let tokens = vec![Token::Number(10.0), Token::Number(3.0), Token::Power]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 1000.0);
+
+        // (-2)^3 → -8.0: целый показатель допустим и для отрицательного основания
+        let tokens = vec![Token::Number(-2.0), Token::Number(3.0), Token::Power]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), -8.0);
+    }
+
+    #[test]
+    fn test_power_fractional_exponent() {
+        // 2^10.5 и 1.0001^12345.6 вычисляются через powf
+        let tokens = vec![Token::Number(2.0), Token::Number(10.5), Token::Power]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 2f64.powf(10.5));
+
+        let tokens = vec![Token::Number(1.0001), Token::Number(12345.6), Token::Power]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 1.0001f64.powf(12345.6));
+
+        // Показатель вне диапазона i32 тоже вычисляется через powf
+        let tokens = vec![Token::Number(1.0), Token::Number(1e12), Token::Power]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_power_zero_base() {
+        // 0^-2 → Ошибка (деление на ноль)
+        let tokens = vec![Token::Number(0.0), Token::Number(-2.0), Token::Power]
+            .into_iter()
+            .collect();
+        assert!(matches!(eval_rpn(&tokens), Err(EvalError::NotFinite(_))));
+    }
+
+    #[test]
+    fn test_overflow() {
+        // 1e308 * 10 → Ошибка
+        let tokens = vec![Token::Number(1e308), Token::Number(10.0), Token::Multiply]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            eval_rpn(&tokens),
+            Err(EvalError::NotFinite("переполнение".to_string()))
+        );
+
+        // 171! → Ошибка
+        let tokens = vec![Token::Number(171.0), Token::Factorial]
+            .into_iter()
+            .collect();
+        assert!(matches!(eval_rpn(&tokens), Err(EvalError::NotFinite(_))));
+    }
+
+    #[test]
+    fn test_eval_twice() {
+        // ОПЗ не расходуется при вычислении
+        let tokens = vec![Token::Number(2.0), Token::Number(3.0), Token::Multiply]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 6.0);
+        assert_eq!(eval_rpn(&tokens).unwrap(), 6.0);
+        assert_eq!(tokens.len(), 3);
+    }
+
+    #[test]
+    fn test_abs() {
+        // abs(-5) → 5.0
+        let tokens = vec![Token::Number(-5.0), Token::Function("abs".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 5.0);
+    }
+
+    #[test]
+    fn test_rounding() {
+        let call = |name: &str, x: f64| {
+            let tokens = vec![Token::Number(x), Token::Function(name.to_string())]
+                .into_iter()
+                .collect();
+            eval_rpn(&tokens).unwrap()
+        };
+
+        assert_eq!(call("floor", 2.7), 2.0);
+        assert_eq!(call("floor", -2.1), -3.0);
+        assert_eq!(call("ceil", 2.1), 3.0);
+        assert_eq!(call("ceil", -2.7), -2.0);
+
+        // Округление половин от нуля
+        assert_eq!(call("round", 2.5), 3.0);
+        assert_eq!(call("round", -2.5), -3.0);
+        assert_eq!(call("round", 2.4), 2.0);
+    }
+
+    #[test]
+    fn test_roots() {
+        // cbrt(-8) → -2.0
+        let tokens = vec![Token::Number(-8.0), Token::Function("cbrt".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), -2.0);
+
+        // root(16, 4) → 2.0
+        let tokens = vec![
+            Token::Number(16.0),
+            Token::Number(4.0),
+            Token::Function("root".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 2.0);
+
+        // root(-16, 4) → Ошибка
+        let tokens = vec![
+            Token::Number(-16.0),
+            Token::Number(4.0),
+            Token::Function("root".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert!(matches!(eval_rpn(&tokens), Err(EvalError::DomainError(_))));
+    }
+
+    #[test]
+    fn test_gcd_lcm() {
+        let call = |name: &str, a: f64, b: f64| {
+            let tokens = vec![
+                Token::Number(a),
+                Token::Number(b),
+                Token::Function(name.to_string()),
+            ]
+            .into_iter()
+            .collect();
+            eval_rpn(&tokens)
+        };
+
+        assert_eq!(call("gcd", 12.0, 18.0).unwrap(), 6.0);
+        assert_eq!(call("gcd", 0.0, 5.0).unwrap(), 5.0);
+        assert_eq!(call("gcd", -12.0, 18.0).unwrap(), 6.0);
+        assert_eq!(call("lcm", 4.0, 6.0).unwrap(), 12.0);
+        assert_eq!(call("lcm", 0.0, 6.0).unwrap(), 0.0);
+        assert!(matches!(
+            call("gcd", 2.5, 5.0),
+            Err(EvalError::DomainError(_))
+        ));
+    }
+
+    #[test]
+    fn test_min_max() {
+        // 3 7 max → 7.0
+        let tokens = vec![
+            Token::Number(3.0),
+            Token::Number(7.0),
+            Token::Function("max".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 7.0);
+
+        // -1 2 min → -1.0
+        let tokens = vec![
+            Token::Number(-1.0),
+            Token::Number(2.0),
+            Token::Function("min".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), -1.0);
+
+        // 3 max → Ошибка
+        let tokens = vec![Token::Number(3.0), Token::Function("max".to_string())]
+            .into_iter()
+            .collect();
+        assert!(matches!(
+            eval_rpn(&tokens),
+            Err(EvalError::InvalidExpression(_))
+        ));
+    }
+
+    #[test]
+    fn test_bitwise() {
+        // 6 & 3 → 2.0
+        let tokens = vec![Token::Number(6.0), Token::Number(3.0), Token::BitAnd]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 2.0);
+
+        // 5 | 2 → 7.0
+        let tokens = vec![Token::Number(5.0), Token::Number(2.0), Token::BitOr]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 7.0);
+
+        // 5 ^^ 1 → 4.0
+        let tokens = vec![Token::Number(5.0), Token::Number(1.0), Token::BitXor]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 4.0);
+
+        // 2.5 & 1 → Ошибка
+        let tokens = vec![Token::Number(2.5), Token::Number(1.0), Token::BitAnd]
+            .into_iter()
+            .collect();
+        assert!(matches!(eval_rpn(&tokens), Err(EvalError::DomainError(_))));
+    }
+
+    #[test]
+    fn test_divide_by_zero_policy() {
+        let rpn = vec![Token::Number(1.0), Token::Number(0.0), Token::Divide]
+            .into_iter()
+            .collect();
+
+        // По умолчанию 1/0 → Ошибка
+        assert_eq!(
+            eval_rpn_with(&rpn, &EvalOptions::default()),
+            Err(EvalError::DivideByZero)
+        );
+
+        // По IEEE 754 1/0 → inf, -1/0 → -inf
+        let ieee = EvalOptions {
+            divide_by_zero: DivByZeroPolicy::Infinity,
+            ..EvalOptions::default()
+        };
+        assert_eq!(eval_rpn_with(&rpn, &ieee).unwrap(), f64::INFINITY);
+
+        let tokens = vec![
+            Token::Number(1.0),
+            Token::UnaryMinus,
+            Token::Number(0.0),
+            Token::Divide,
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(eval_rpn_with(&tokens, &ieee).unwrap(), f64::NEG_INFINITY);
+
+        // 0/0 не определено и при политике Infinity
+        let tokens = vec![Token::Number(0.0), Token::Number(0.0), Token::Divide]
+            .into_iter()
+            .collect();
+        assert!(matches!(
+            eval_rpn_with(&tokens, &ieee),
+            Err(EvalError::NotFinite(_))
+        ));
+    }
+
+    #[test]
+    fn test_step_limit() {
+        // 1 + 1 + ... + 1: 99 сложений
+        let mut tokens: VecDeque<Token> = VecDeque::from([Token::Number(1.0)]);
+        for _ in 0..99 {
+            tokens.push_back(Token::Number(1.0));
+            tokens.push_back(Token::Plus);
+        }
+
+        let limited = |max| EvalOptions {
+            max_steps: Some(max),
+            ..EvalOptions::default()
+        };
+        assert_eq!(eval_rpn_with(&tokens, &limited(99)).unwrap(), 100.0);
+        assert_eq!(
+            eval_rpn_with(&tokens, &limited(10)),
+            Err(EvalError::StepLimitExceeded(10))
+        );
+        assert_eq!(eval_rpn(&tokens).unwrap(), 100.0);
+    }
+
+    #[test]
+    fn test_zero_epsilon() {
+        // 1 / 1e-20
+        let tokens = vec![Token::Number(1.0), Token::Number(1e-20), Token::Divide]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn(&tokens).unwrap(), 1e20);
+
+        let tolerant = EvalOptions {
+            zero_epsilon: 1e-12,
+            ..EvalOptions::default()
+        };
+        assert_eq!(
+            eval_rpn_with(&tokens, &tolerant),
+            Err(EvalError::DivideByZero)
+        );
+
+        // Делители не меньше epsilon не затрагиваются
+        let tokens = vec![Token::Number(1.0), Token::Number(1e-3), Token::Divide]
+            .into_iter()
+            .collect();
+        assert_eq!(eval_rpn_with(&tokens, &tolerant).unwrap(), 1000.0);
+
+        // Остаток от деления проверяется так же
+        let tokens = vec![Token::Number(5.0), Token::Number(-1e-15), Token::Modulo]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            eval_rpn_with(&tokens, &tolerant),
+            Err(EvalError::DivideByZero)
+        );
+    }
+
+    #[test]
+    fn test_exact_mode() {
+        let exact = EvalOptions {
+            exact: true,
+            ..EvalOptions::default()
+        };
+        let rpn = |input: &str| to_rpn(crate::parser::tokenize(input).unwrap()).unwrap();
+
+        // 1/3*3 и 1/3 + 1/3 + 1/3 в точном режиме равны ровно 1
+        assert_eq!(eval_rpn_with(&rpn("1/3*3"), &exact).unwrap(), 1.0);
+        assert_eq!(eval_rpn_with(&rpn("1/3 + 1/3 + 1/3"), &exact).unwrap(), 1.0);
+        assert_eq!(eval_rpn_with(&rpn("1/10 + 2/10"), &exact).unwrap(), 0.3);
+        assert_ne!(eval_rpn(&rpn("1/10 + 2/10")).unwrap(), 0.3);
+        assert_eq!(eval_rpn_with(&rpn("(2/3)^-2 * 4/9"), &exact).unwrap(), 1.0);
+        assert_eq!(eval_rpn_with(&rpn("-1/10 + 3/10"), &exact).unwrap(), 0.2);
+
+        // Функции и дробные числа вычисляются в f64
+        assert_eq!(eval_rpn_with(&rpn("sqrt(4) / 2"), &exact).unwrap(), 1.0);
+        assert!(approx_eq(
+            eval_rpn_with(&rpn("0.1 + 0.2"), &exact).unwrap(),
+            0.3,
+            1e-12
+        ));
+
+        // Ошибки те же, что и без точного режима
+        assert_eq!(
+            eval_rpn_with(&rpn("1 / (3 - 3)"), &exact),
+            Err(EvalError::DivideByZero)
+        );
+    }
+
+    #[test]
+    fn test_integers_only() {
+        let integers = EvalOptions {
+            integers_only: true,
+            ..EvalOptions::default()
+        };
+        let rpn = |input: &str| to_rpn(crate::parser::tokenize(input).unwrap()).unwrap();
+
+        assert_eq!(eval_rpn_with(&rpn("6/2"), &integers).unwrap(), 3.0);
+        assert_eq!(eval_rpn_with(&rpn("5! / 3!"), &integers).unwrap(), 20.0);
+        assert!(matches!(
+            eval_rpn_with(&rpn("7/2"), &integers),
+            Err(EvalError::DomainError(_))
+        ));
+        // Дробный промежуточный результат — ошибка, даже если итог целый
+        assert!(matches!(
+            eval_rpn_with(&rpn("7/2*2"), &integers),
+            Err(EvalError::DomainError(_))
+        ));
+        assert_eq!(eval_rpn(&rpn("7/2")).unwrap(), 3.5);
+
+        // Дробное число или константа как итог выражения — тоже ошибка
+        for input in ["2.5", "pi", "-2.5"] {
+            assert!(
+                matches!(
+                    eval_rpn_with(&rpn(input), &integers),
+                    Err(EvalError::DomainError(_))
+                ),
+                "input: {}",
+                input
+            );
+        }
+        assert_eq!(eval_rpn_with(&rpn("0.5 * 4"), &integers).unwrap(), 2.0);
+    }
+
+    #[test]
+    fn test_nan_result() {
+        // (-8)^0.5 → Ошибка
+        let tokens = vec![Token::Number(-8.0), Token::Number(0.5), Token::Power]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            eval_rpn(&tokens),
+            Err(EvalError::NotFinite(
+                "результат не определён (NaN)".to_string()
+            ))
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests_rpn_to_infix {
+    use super::*;
+    use crate::parser::Token;
+
+    #[test]
+    fn test_binary_operators() {
+        // 2 3 4 * +
+        let rpn = vec![
+            Token::Number(2.0),
+            Token::Number(3.0),
+            Token::Number(4.0),
+            Token::Multiply,
+            Token::Plus,
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(rpn_to_infix(&rpn), "(2 + (3 * 4))");
+
+        // 1 2 - 3 -
+        let rpn = vec![
+            Token::Number(1.0),
+            Token::Number(2.0),
+            Token::Minus,
+            Token::Number(3.0),
+            Token::Minus,
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(rpn_to_infix(&rpn), "((1 - 2) - 3)");
+    }
+
+    #[test]
+    fn test_power_right_associativity() {
+        // 2 3 2 ^ ^
+        let rpn = vec![
+            Token::Number(2.0),
+            Token::Number(3.0),
+            Token::Number(2.0),
+            Token::Power,
+            Token::Power,
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(rpn_to_infix(&rpn), "(2 ^ (3 ^ 2))");
+    }
+
+    #[test]
+    fn test_unary_minus() {
+        // 2 ~ 2 ^ → -2 ^ 2 разбирается как (-2)^2
+        let rpn = vec![
+            Token::Number(2.0),
+            Token::UnaryMinus,
+            Token::Number(2.0),
+            Token::Power,
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(rpn_to_infix(&rpn), "((-2) ^ 2)");
+    }
+
+    #[test]
+    fn test_functions_and_postfix() {
+        // 1 2 + sin 3 !
+        let rpn = vec![
+            Token::Number(1.0),
+            Token::Number(2.0),
+            Token::Plus,
+            Token::Function("sin".to_string()),
+            Token::Number(3.0),
+            Token::Factorial,
+            Token::Multiply,
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(rpn_to_infix(&rpn), "(sin(1 + 2) * (3!))");
+
+        // 4 sqrt
+        let rpn = vec![Token::Number(4.0), Token::Function("sqrt".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(rpn_to_infix(&rpn), "sqrt(4)");
+    }
+
+    #[test]
+    fn test_missing_operand() {
+        let rpn = vec![Token::Number(1.0), Token::Plus].into_iter().collect();
+        assert_eq!(rpn_to_infix(&rpn), "(? + 1)");
     }
 }
@@ -0,0 +1,36 @@
+// Вспомогательные функции для тестов
+
+/// Сравнивает числа с точностью `eps`. NaN не равен ничему, в том числе NaN;
+/// бесконечности равны только бесконечностям того же знака.
+/// Пример: approx_eq(0.1 + 0.2, 0.3, 1e-12) → true
+pub fn approx_eq(a: f64, b: f64, eps: f64) -> bool {
+    if a.is_nan() || b.is_nan() {
+        return false;
+    }
+    if a.is_infinite() || b.is_infinite() {
+        return a == b;
+    }
+    (a - b).abs() <= eps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::approx_eq;
+
+    #[test]
+    fn test_approx_eq() {
+        assert!(approx_eq(0.1 + 0.2, 0.3, 1e-12));
+        assert!(approx_eq(1.0, 1.0, 0.0));
+        assert!(approx_eq(1.0, 1.0005, 1e-3));
+        assert!(!approx_eq(1.0, 1.1, 1e-3));
+    }
+
+    #[test]
+    fn test_approx_eq_non_finite() {
+        assert!(!approx_eq(f64::NAN, f64::NAN, 1.0));
+        assert!(!approx_eq(f64::NAN, 0.0, f64::INFINITY));
+        assert!(approx_eq(f64::INFINITY, f64::INFINITY, 1e-12));
+        assert!(!approx_eq(f64::INFINITY, f64::NEG_INFINITY, 1e-12));
+        assert!(!approx_eq(f64::INFINITY, 1e308, f64::INFINITY));
+    }
+}
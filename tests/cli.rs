@@ -0,0 +1,407 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn run_cli(args: &[&str]) -> (String, String, bool) {
+    let output = Command::new(env!("CARGO_BIN_EXE_calculator"))
+        .args(args)
+        .output()
+        .expect("не удалось запустить калькулятор");
+    (
+        String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        output.status.success(),
+    )
+}
+
+#[test]
+fn test_cli_single_quoted_argument() {
+    let (stdout, _, ok) = run_cli(&["2 + 3"]);
+    assert!(ok);
+    assert_eq!(stdout, "5");
+}
+
+#[test]
+fn test_cli_separate_arguments() {
+    let (stdout, _, ok) = run_cli(&["2", "+", "3"]);
+    assert!(ok);
+    assert_eq!(stdout, "5");
+
+    let (stdout, _, ok) = run_cli(&["(1", "+", "2)", "*", "3"]);
+    assert!(ok);
+    assert_eq!(stdout, "9");
+}
+
+#[test]
+fn test_cli_digit_groups() {
+    // Разряды разделяются только "_": аргументы склеиваются через пробел,
+    // и "1 000" не превращается в 1000
+    let (stdout, _, ok) = run_cli(&["1_000_000", "+", "1"]);
+    assert!(ok);
+    assert_eq!(stdout, "1000001");
+
+    let (_, stderr, ok) = run_cli(&["1", "000"]);
+    assert!(!ok);
+    assert!(stderr.contains("Пропущен оператор"));
+
+    let (_, _, ok) = run_cli(&["1 000 000"]);
+    assert!(!ok);
+}
+
+#[test]
+fn test_cli_number_formatting() {
+    let (stdout, _, ok) = run_cli(&["0.1 + 0.2"]);
+    assert!(ok);
+    assert_eq!(stdout, "0.3");
+
+    let (stdout, _, ok) = run_cli(&["10 / 4"]);
+    assert!(ok);
+    assert_eq!(stdout, "2.5");
+}
+
+#[test]
+fn test_cli_error_exit_code() {
+    let (_, stderr, ok) = run_cli(&["1", "/", "0"]);
+    assert!(!ok);
+    assert_eq!(stderr, "Error: Деление на 0.");
+}
+
+// Запускает калькулятор и возвращает код выхода
+fn exit_code(args: &[&str]) -> Option<i32> {
+    Command::new(env!("CARGO_BIN_EXE_calculator"))
+        .args(args)
+        .output()
+        .expect("не удалось запустить калькулятор")
+        .status
+        .code()
+}
+
+#[test]
+fn test_cli_exit_codes() {
+    assert_eq!(exit_code(&["2 + 3"]), Some(0));
+    assert_eq!(exit_code(&["1 / 0"]), Some(1));
+    assert_eq!(exit_code(&["--json", "1 / 0"]), Some(1));
+    assert_eq!(exit_code(&["--unknown", "2 + 3"]), Some(2));
+    assert_eq!(exit_code(&["--precision", "abc", "1"]), Some(2));
+    assert_eq!(
+        exit_code(&["--file", "/nonexistent/calculator.txt"]),
+        Some(64)
+    );
+
+    let (_, stderr, _) = run_cli(&["--unknown", "2 + 3"]);
+    assert_eq!(stderr, "Error: неизвестный флаг '--unknown'");
+}
+
+#[test]
+fn test_cli_tokens_dump() {
+    let (stdout, _, ok) = run_cli(&["--tokens", "2 + 3*4"]);
+    assert!(ok);
+    assert_eq!(stdout, "2 + 3 * 4");
+
+    // Числа печатаются так, как они записаны
+    let (stdout, _, ok) = run_cli(&["--tokens", "2.50 * 1e3"]);
+    assert!(ok);
+    assert_eq!(stdout, "2.50 * 1e3");
+
+    let (stdout, _, ok) = run_cli(&["--tokens", "-sqrt(16)!"]);
+    assert!(ok);
+    assert_eq!(stdout, "- sqrt ( 16 ) !");
+}
+
+#[test]
+fn test_cli_explain() {
+    let (stdout, _, ok) = run_cli(&["--explain", "2 + 3 * 4"]);
+    assert!(ok);
+    assert_eq!(
+        stdout,
+        "2 → [2]\n3 → [2, 3]\n4 → [2, 3, 4]\n* → [2, 12]\n+ → [14]\n= 14"
+    );
+
+    let (_, stderr, ok) = run_cli(&["--explain", "1 / 0"]);
+    assert!(!ok);
+    assert_eq!(stderr, "Error: Деление на 0.");
+
+    // Настройки вычисления и --json учитываются
+    let (stdout, _, ok) = run_cli(&["--percent-of", "--explain", "100 + 20%"]);
+    assert!(ok);
+    assert!(stdout.ends_with("+ → [120]\n= 120"));
+
+    let (stdout, _, ok) = run_cli(&["--lenient", "--dms", "--explain", "(90d30m"]);
+    assert!(ok);
+    assert!(stdout.ends_with("= 90.5"));
+
+    let (stdout, _, ok) = run_cli(&["--json", "--explain", "2 * 3"]);
+    assert!(ok);
+    assert_eq!(
+        stdout,
+        r#"{"ok":true,"result":6.0,"steps":["2 → [2]","3 → [2, 3]","* → [6]"]}"#
+    );
+}
+
+#[test]
+fn test_cli_rpn_dump() {
+    let (stdout, _, ok) = run_cli(&["--rpn", "2 + 3 * 4"]);
+    assert!(ok);
+    assert_eq!(stdout, "2 3 4 * +");
+
+    let (stdout, _, ok) = run_cli(&["--rpn", "2.50 * 0.10"]);
+    assert!(ok);
+    assert_eq!(stdout, "2.50 0.10 *");
+
+    let (stdout, _, ok) = run_cli(&["--rpn", "(2 + 3) * 2^3^2"]);
+    assert!(ok);
+    assert_eq!(stdout, "2 3 + 2 3 2 ^ ^ *");
+
+    let (_, stderr, ok) = run_cli(&["--rpn", "(2 + 3"]);
+    assert!(!ok);
+    assert!(stderr.starts_with("Error:"));
+}
+
+#[test]
+fn test_cli_leading_minus_expression() {
+    let (stdout, _, ok) = run_cli(&["--", "-5", "+", "3"]);
+    assert!(ok);
+    assert_eq!(stdout, "-2");
+
+    let (stdout, _, ok) = run_cli(&["--eval", "-5 + 3"]);
+    assert!(ok);
+    assert_eq!(stdout, "-2");
+
+    // Флаги перед "--" по-прежнему действуют
+    let (stdout, _, ok) = run_cli(&["--json", "--", "-q"]);
+    assert!(!ok);
+    assert!(stdout.starts_with(r#"{"ok":false"#));
+}
+
+#[test]
+fn test_cli_version() {
+    let (stdout, _, ok) = run_cli(&["--version"]);
+    assert!(ok);
+    assert_eq!(stdout, format!("calculator {}", env!("CARGO_PKG_VERSION")));
+
+    // Флаг печатает версию и при наличии выражения
+    let (stdout, _, ok) = run_cli(&["--version", "2 + 3"]);
+    assert!(ok);
+    assert!(stdout.ends_with(env!("CARGO_PKG_VERSION")));
+}
+
+#[test]
+fn test_cli_json_result() {
+    let (stdout, _, ok) = run_cli(&["--json", "2 + 3"]);
+    assert!(ok);
+    assert_eq!(stdout, r#"{"ok":true,"result":5.0}"#);
+}
+
+#[test]
+fn test_cli_json_error() {
+    let (stdout, stderr, ok) = run_cli(&["1 / 0", "--json"]);
+    assert!(!ok);
+    assert_eq!(stdout, r#"{"ok":false,"error":"Деление на 0."}"#);
+    assert!(stderr.is_empty());
+}
+
+#[test]
+fn test_cli_seed() {
+    let (first, _, ok) = run_cli(&["--seed", "42", "rand(); rand(1, 100)"]);
+    assert!(ok);
+    let (second, _, _) = run_cli(&["--seed", "42", "rand(); rand(1, 100)"]);
+    assert_eq!(first, second);
+    assert_eq!(first.lines().count(), 2);
+
+    let (_, stderr, ok) = run_cli(&["--seed", "abc", "rand()"]);
+    assert!(!ok);
+    assert!(stderr.contains("--seed"));
+}
+
+#[test]
+fn test_cli_format() {
+    let (stdout, _, ok) = run_cli(&["--format", "hex", "255"]);
+    assert!(ok);
+    assert_eq!(stdout, "0xff");
+
+    let (stdout, _, ok) = run_cli(&["--format", "bin", "2^4 + 1"]);
+    assert!(ok);
+    assert_eq!(stdout, "0b10001");
+
+    let (stdout, _, ok) = run_cli(&["--format", "hex", "10 / 4"]);
+    assert!(ok);
+    assert_eq!(stdout, "2.5 (не целое число, показано в десятичной записи)");
+
+    let (_, stderr, ok) = run_cli(&["--format", "oct", "8"]);
+    assert!(!ok);
+    assert!(stderr.contains("--format"));
+}
+
+#[test]
+fn test_cli_precision() {
+    let (stdout, _, ok) = run_cli(&["--precision", "2", "2 / 3"]);
+    assert!(ok);
+    assert_eq!(stdout, "0.67");
+
+    let (stdout, _, ok) = run_cli(&["10 / 4", "--precision", "0"]);
+    assert!(ok);
+    assert_eq!(stdout, "2");
+
+    let (_, stderr, ok) = run_cli(&["--precision", "abc", "1 + 1"]);
+    assert!(!ok);
+    assert!(stderr.contains("--precision"));
+}
+
+// Запускает калькулятор с данными на stdin
+fn run_cli_stdin(args: &[&str], stdin: &str) -> (String, bool) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_calculator"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("не удалось запустить калькулятор");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    (
+        String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        output.status.success(),
+    )
+}
+
+#[test]
+fn test_cli_batch() {
+    let (stdout, ok) = run_cli_stdin(&["--batch"], "2 + 3\n\n10 / 4\n2 * 3\n");
+    assert!(ok);
+    assert_eq!(stdout, "5\n2.5\n6");
+}
+
+#[test]
+fn test_cli_batch_continues_after_errors() {
+    let (stdout, ok) = run_cli_stdin(&["--batch"], "1 / 0\n2 +\n7 - 2\n");
+    assert!(!ok);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "Error: Деление на 0.");
+    assert!(lines[1].starts_with("Error: "));
+    assert_eq!(lines[2], "5");
+}
+
+#[test]
+fn test_cli_batch_json() {
+    let (stdout, ok) = run_cli_stdin(&["--batch", "--json"], "1 + 1\n1 / 0\n");
+    assert!(!ok);
+    assert_eq!(
+        stdout,
+        "{\"ok\":true,\"result\":2.0}\n{\"ok\":false,\"error\":\"Деление на 0.\"}"
+    );
+}
+
+#[test]
+fn test_cli_file() {
+    let path = std::env::temp_dir().join(format!("calculator_file_{}.txt", std::process::id()));
+    std::fs::write(
+        &path,
+        "# Расчёт\n2 + 3\n\n  # ещё комментарий\nans * 2\n10 / 4\n",
+    )
+    .unwrap();
+
+    let (stdout, _, ok) = run_cli(&["--file", path.to_str().unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+    assert!(ok);
+    assert_eq!(stdout, "5\n10\n2.5");
+}
+
+#[test]
+fn test_cli_file_missing() {
+    let (_, stderr, ok) = run_cli(&["--file", "/nonexistent/calculator.txt"]);
+    assert!(!ok);
+    assert!(stderr.starts_with("Error: не удалось прочитать файл"));
+}
+
+#[test]
+fn test_cli_percent_of() {
+    let (stdout, ok) = run_cli_stdin(&["--batch"], "100 + 20%\n");
+    assert!(ok);
+    assert_eq!(stdout, "100.2");
+
+    let (stdout, ok) = run_cli_stdin(&["--batch", "--percent-of"], "100 + 20%\n100 - 20%\n");
+    assert!(ok);
+    assert_eq!(stdout, "120\n80");
+}
+
+#[test]
+fn test_cli_dms() {
+    let (stdout, ok) = run_cli_stdin(&["--batch", "--dms"], "90d30m\n1d + 30m\n");
+    assert!(ok);
+    assert_eq!(stdout, "90.5\n1.5");
+
+    let (_, ok) = run_cli_stdin(&["--batch"], "90d30m\n");
+    assert!(!ok);
+
+    let (stdout, _, ok) = run_cli(&["--dms", "90d30m"]);
+    assert!(ok);
+    assert_eq!(stdout, "90.5");
+
+    let (stdout, _, ok) = run_cli(&["--dms", "--tokens", "90d30m + 1"]);
+    assert!(ok);
+    assert_eq!(stdout, "90d30m + 1");
+}
+
+#[test]
+fn test_cli_lenient() {
+    let (stdout, ok) = run_cli_stdin(&["--batch", "--lenient"], "(2+3\n");
+    assert!(ok);
+    assert_eq!(stdout, "5");
+
+    let (stdout, ok) = run_cli_stdin(&["--batch"], "(2+3\n");
+    assert!(!ok);
+    assert!(stdout.starts_with("Error: Не совпадают скобки"));
+    let (stdout, _, ok) = run_cli(&["--lenient", "(2+3"]);
+    assert!(ok);
+    assert_eq!(stdout, "5");
+    let (stdout, _, ok) = run_cli(&["--lenient", "--rpn", "(2+3"]);
+    assert!(ok);
+    assert_eq!(stdout, "2 3 +");
+    let (_, stderr, ok) = run_cli(&["(2+3"]);
+    assert!(!ok);
+    assert!(stderr.contains("Не совпадают скобки"));
+}
+
+#[test]
+fn test_cli_no_color() {
+    let (stdout, _, ok) = run_cli(&["--no-color", "2 + 3"]);
+    assert!(ok);
+    assert_eq!(stdout, "5");
+
+    // Без выражения флаг не переводит калькулятор в режим CLI
+    let (stdout, ok) = run_cli_stdin(&["--no-color", "--no-history"], "2 + 3\nexit\n");
+    assert!(ok);
+    assert!(stdout.contains("5"));
+    assert!(!stdout.contains('\x1b'));
+}
+
+#[test]
+fn test_cli_repl_history_refs() {
+    // Номера "$N" совпадают с номерами в "history", включая строки с ошибкой
+    let (stdout, ok) = run_cli_stdin(
+        &["--no-color", "--no-history", "--quiet"],
+        "1/0\n5\n$2 * 2\n$1\nhistory\nexit\n",
+    );
+    assert!(ok);
+    let lines: Vec<&str> = stdout.lines().skip(1).collect();
+    assert_eq!(lines[..2], ["5", "10"]);
+    assert!(lines[2].starts_with("1: 1/0 → "));
+    assert_eq!(lines[3..5], ["2: 5 = 5", "3: $2 * 2 = 10"]);
+    // Строка с ошибкой под номером 1 результата не имеет
+    assert!(lines[5].starts_with("4: $1 → "));
+}
+
+#[test]
+fn test_cli_sequence() {
+    let (stdout, _, ok) = run_cli(&["1+1; 2*3; 4-1"]);
+    assert!(ok);
+    assert_eq!(stdout, "2\n6\n3");
+
+    let (_, stderr, ok) = run_cli(&["1 + 1;"]);
+    assert!(!ok);
+    assert!(stderr.starts_with("Error: "));
+}
@@ -0,0 +1,967 @@
+use calculator::{
+    CompiledExpr, EvalResult, Number,
+    error::{CalcError, EvalError, ParseError, SpannedError},
+    evaluate, evaluate_detailed, evaluate_many, evaluate_sequence, evaluate_sequence_with,
+    evaluate_spanned, evaluate_with_ans, evaluate_with_vars, parser, random, rpn,
+    testing::approx_eq,
+    validate,
+};
+use std::collections::HashMap;
+
+const EPS: f64 = 1e-12;
+
+#[test]
+fn test_simple_expression() {
+    assert_eq!(evaluate("2 + 3").unwrap(), 5.0);
+    assert_eq!(evaluate("10 - 4").unwrap(), 6.0);
+    assert_eq!(evaluate("3 * 4").unwrap(), 12.0);
+    assert_eq!(evaluate("8 / 2").unwrap(), 4.0);
+}
+
+#[test]
+fn test_operator_precedence() {
+    assert_eq!(evaluate("2 + 3 * 4").unwrap(), 14.0); // 2 + (3*4)
+    assert_eq!(evaluate("10 - 2 * 3").unwrap(), 4.0); // 10 - (2*3)
+    assert_eq!(evaluate("1 + 2 * 3 - 4").unwrap(), 3.0); // 1 + (2*3) - 4
+}
+
+#[test]
+fn test_parentheses() {
+    assert_eq!(evaluate("(2 + 3) * 4").unwrap(), 20.0); // (2+3)*4
+    assert_eq!(evaluate("((2 + 3) * 4) - 5").unwrap(), 15.0); // ((2+3)*4)-5
+    assert_eq!(evaluate("2 * (3 + (4 * 5))").unwrap(), 46.0); // 2*(3+(4*5))
+}
+
+#[test]
+fn test_unary_minus() {
+    assert_eq!(evaluate("-5").unwrap(), -5.0);
+    assert_eq!(evaluate("2 + (-3)").unwrap(), -1.0);
+    assert_eq!(evaluate("-(-4)").unwrap(), 4.0);
+    assert_eq!(evaluate("2^(-1)").unwrap(), 0.5);
+    assert_eq!(evaluate("2*-3").unwrap(), -6.0);
+    assert_eq!(evaluate("(-4)").unwrap(), -4.0);
+    assert_eq!(evaluate("(4)-1").unwrap(), 3.0);
+}
+
+#[test]
+fn test_unary_plus() {
+    assert_eq!(evaluate("+5").unwrap(), 5.0);
+    assert_eq!(evaluate("2 + +3").unwrap(), 5.0);
+    assert_eq!(evaluate("--5").unwrap(), 5.0);
+    assert_eq!(evaluate("-+5").unwrap(), -5.0);
+    assert_eq!(evaluate("2 * +(1 + 2)").unwrap(), 6.0);
+    assert_eq!(evaluate("2 - -3").unwrap(), 5.0);
+}
+
+#[test]
+fn test_power_right_associativity() {
+    assert_eq!(evaluate("2^3^2").unwrap(), 512.0); // 2^(3^2)
+    assert_eq!(evaluate("2^(3^2)").unwrap(), 512.0);
+    assert_eq!(evaluate("(2^3)^2").unwrap(), 64.0);
+    assert_eq!(evaluate("3^2^2").unwrap(), 81.0); // 3^(2^2) = 3^4
+}
+
+#[test]
+fn test_power_mixed_with_multiply() {
+    assert_eq!(evaluate("2^3*4").unwrap(), 32.0); // (2^3)*4
+    assert_eq!(evaluate("4*2^3").unwrap(), 32.0); // 4*(2^3)
+    assert_eq!(evaluate("2^3^2*2").unwrap(), 1024.0); // (2^(3^2))*2
+    assert_eq!(evaluate("2*3^2^1+1").unwrap(), 19.0); // 2*(3^(2^1))+1
+    assert_eq!(evaluate("-2^2").unwrap(), -4.0); // -(2^2)
+}
+
+#[test]
+fn test_power_negative_exponent_precision() {
+    assert_eq!(evaluate("10^-23 == 1e-23").unwrap(), 1.0);
+    assert_eq!(*evaluate("10^-300").unwrap(), 1e-300);
+}
+
+#[test]
+fn test_double_star_power() {
+    assert_eq!(evaluate("2 ^ 3").unwrap(), 8.0);
+    assert_eq!(evaluate("2 ** 3").unwrap(), 8.0);
+    assert_eq!(evaluate("2 ** 3 ** 2").unwrap(), 512.0);
+    assert_eq!(evaluate("3 * 2**2").unwrap(), 12.0);
+
+    let err = evaluate("2 * * 3").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Eval(EvalError::InvalidExpression(_))
+    ));
+}
+
+#[test]
+fn test_trig_functions() {
+    assert_eq!(evaluate("sin(0)").unwrap(), 0.0);
+    assert_eq!(evaluate("cos(0) + 1").unwrap(), 2.0);
+    assert!(approx_eq(*evaluate("cos(3.14159)").unwrap(), -1.0, 1e-9));
+    assert!(approx_eq(
+        *evaluate("2 * sin(1 + 0.5)").unwrap(),
+        2.0 * 1.5f64.sin(),
+        EPS
+    ));
+    assert_eq!(evaluate("-tan(0)").unwrap(), 0.0);
+
+    let err = evaluate("foo(1)").unwrap_err();
+    assert!(matches!(err, CalcError::Parse(ParseError::InvalidToken(_))));
+}
+
+#[test]
+fn test_sqrt() {
+    assert_eq!(evaluate("sqrt(16)").unwrap(), 4.0);
+    assert!(approx_eq(
+        *evaluate("sqrt(2)").unwrap(),
+        std::f64::consts::SQRT_2,
+        EPS
+    ));
+    assert_eq!(evaluate("sqrt(3^2 + 4^2)").unwrap(), 5.0);
+
+    let err = evaluate("sqrt(-4)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+    assert_eq!(
+        err.to_string(),
+        "Ошибка области определения: Квадратный корень из отрицательного числа -4"
+    );
+}
+
+#[test]
+fn test_abs() {
+    assert_eq!(evaluate("abs(-5)").unwrap(), 5.0);
+    assert_eq!(evaluate("abs(3 - 10)").unwrap(), 7.0);
+    assert_eq!(evaluate("abs(-2.5)").unwrap(), 2.5);
+    assert_eq!(evaluate("2abs(-1) + abs(4)").unwrap(), 6.0);
+
+    // Опечатка в имени функции
+    let err = evaluate("asb(1)").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Некорректный символ: Неизвестный идентификатор: 'asb'"
+    );
+}
+
+#[test]
+fn test_rounding() {
+    assert_eq!(evaluate("floor(2.7)").unwrap(), 2.0);
+    assert_eq!(evaluate("ceil(2.1)").unwrap(), 3.0);
+    assert_eq!(evaluate("round(2.5)").unwrap(), 3.0);
+    assert_eq!(evaluate("round(-2.5)").unwrap(), -3.0);
+    assert_eq!(evaluate("round(10 / 4) * 2").unwrap(), 6.0);
+    assert_eq!(evaluate("trunc(2.9)").unwrap(), 2.0);
+    assert_eq!(evaluate("trunc(-2.9)").unwrap(), -2.0);
+}
+
+#[test]
+fn test_sign() {
+    assert_eq!(evaluate("sign(-3)").unwrap(), -1.0);
+    assert_eq!(evaluate("sign(4)").unwrap(), 1.0);
+    assert_eq!(evaluate("sign(0.001)").unwrap(), 1.0);
+
+    // В отличие от f64::signum, знак нуля — ноль, а не ±1
+    let zero = evaluate("sign(0)").unwrap();
+    assert_eq!(zero, 0.0);
+    assert!(zero.is_sign_positive());
+    let zero = evaluate("sign(-0)").unwrap();
+    assert_eq!(zero, 0.0);
+    assert!(zero.is_sign_positive());
+}
+
+#[test]
+fn test_roots() {
+    assert_eq!(evaluate("cbrt(27)").unwrap(), 3.0);
+    assert_eq!(evaluate("cbrt(-8)").unwrap(), -2.0);
+    assert!(approx_eq(*evaluate("root(8, 3)").unwrap(), 2.0, EPS));
+    assert!(approx_eq(*evaluate("root(-32, 5)").unwrap(), -2.0, EPS));
+    assert_eq!(evaluate("root(81, 2)").unwrap(), 9.0);
+
+    // Корень чётной степени из отрицательного числа не определён
+    let err = evaluate("root(-4, 2)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+    let err = evaluate("root(8, 0)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+}
+
+#[test]
+fn test_logarithms() {
+    assert_eq!(evaluate("ln(e)").unwrap(), 1.0);
+    assert_eq!(evaluate("ln(1)").unwrap(), 0.0);
+    assert_eq!(evaluate("log(100)").unwrap(), 2.0);
+    assert_eq!(evaluate("log(1000)").unwrap(), 3.0);
+    assert_eq!(evaluate("2log(10)").unwrap(), 2.0);
+
+    let err = evaluate("ln(-1)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+    let err = evaluate("log(0)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+}
+
+#[test]
+fn test_exp() {
+    assert_eq!(evaluate("exp(0)").unwrap(), 1.0);
+    assert!(approx_eq(
+        *evaluate("exp(1)").unwrap(),
+        std::f64::consts::E,
+        EPS
+    ));
+    assert!(approx_eq(*evaluate("ln(exp(2))").unwrap(), 2.0, EPS));
+    assert!(approx_eq(*evaluate("exp(-1) * e").unwrap(), 1.0, EPS));
+
+    let err = evaluate("exp(1000)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::NotFinite(_))));
+}
+
+#[test]
+fn test_reciprocal_trig() {
+    assert_eq!(evaluate("sec(0)").unwrap(), 1.0);
+    assert!(approx_eq(*evaluate("cot(pi/4)").unwrap(), 1.0, EPS));
+    assert!(approx_eq(*evaluate("csc(pi/6)").unwrap(), 2.0, EPS));
+    assert!(approx_eq(*evaluate("cot(pi/2)").unwrap(), 0.0, EPS));
+
+    let err = evaluate("csc(0)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+    let err = evaluate("cot(0)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+    let err = evaluate("sec(pi/2)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+    let err = evaluate("csc(pi)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+    let err = evaluate("cot(-3 * pi)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+    let err = evaluate("sec(3 * pi / 2)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+
+    // Малый ненулевой аргумент — не полюс
+    assert!(approx_eq(*evaluate("csc(1e-16)").unwrap(), 1e16, 1.0));
+    assert!(approx_eq(*evaluate("cot(1e-16)").unwrap(), 1e16, 1.0));
+    assert!(approx_eq(*evaluate("csc(-1e-300)").unwrap(), -1e300, 1e285));
+}
+
+#[test]
+fn test_inverse_trig() {
+    use std::f64::consts::PI;
+
+    assert!(approx_eq(*evaluate("asin(1)").unwrap(), PI / 2.0, EPS));
+    assert!(approx_eq(*evaluate("acos(-1)").unwrap(), PI, EPS));
+    assert!(approx_eq(*evaluate("atan(1)").unwrap(), PI / 4.0, EPS));
+    assert!(approx_eq(*evaluate("sin(asin(0.3))").unwrap(), 0.3, EPS));
+
+    assert!(approx_eq(*evaluate("atan2(1, 1)").unwrap(), PI / 4.0, EPS));
+    assert!(approx_eq(*evaluate("atan2(0, -1)").unwrap(), PI, EPS));
+    assert!(approx_eq(
+        *evaluate("atan2(-1, 0)").unwrap(),
+        -PI / 2.0,
+        EPS
+    ));
+
+    let err = evaluate("asin(2)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+    let err = evaluate("acos(-1.5)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+}
+
+#[test]
+fn test_hyperbolic() {
+    assert_eq!(evaluate("sinh(0)").unwrap(), 0.0);
+    assert_eq!(evaluate("cosh(0)").unwrap(), 1.0);
+    assert_eq!(evaluate("tanh(0)").unwrap(), 0.0);
+    assert!(approx_eq(
+        *evaluate("cosh(1)").unwrap(),
+        1.5430806348152437,
+        EPS
+    ));
+    assert!(approx_eq(
+        *evaluate("cosh(2)^2 - sinh(2)^2").unwrap(),
+        1.0,
+        1e-9
+    ));
+    assert!(approx_eq(*evaluate("tanh(1000)").unwrap(), 1.0, EPS));
+}
+
+#[test]
+fn test_constants() {
+    assert_eq!(evaluate("pi").unwrap(), std::f64::consts::PI);
+    assert_eq!(evaluate("e").unwrap(), std::f64::consts::E);
+    assert_eq!(evaluate("2 * pi").unwrap(), std::f64::consts::TAU);
+    assert!(approx_eq(
+        *evaluate("e ^ 2").unwrap(),
+        std::f64::consts::E.powi(2),
+        EPS
+    ));
+    assert!(approx_eq(*evaluate("cos(pi) + 1").unwrap(), 0.0, EPS));
+    assert_eq!(evaluate("-pi").unwrap(), -std::f64::consts::PI);
+
+    assert!(approx_eq(
+        *evaluate("tau").unwrap(),
+        2.0 * std::f64::consts::PI,
+        EPS
+    ));
+    assert!(approx_eq(*evaluate("phi").unwrap(), 1.6180339887, 1e-10));
+    assert!(approx_eq(*evaluate("phi^2 - phi").unwrap(), 1.0, EPS));
+    assert_eq!(*evaluate("2phi").unwrap(), 2.0 * *evaluate("phi").unwrap());
+
+    // "2e" — произведение на константу, "2e5" — экспоненциальная запись
+    assert_eq!(evaluate("2e5").unwrap(), 200000.0);
+    assert!(approx_eq(*evaluate("2e").unwrap(), 5.43656365691809, EPS));
+    assert_eq!(evaluate("2pi").unwrap(), std::f64::consts::TAU);
+    assert!(approx_eq(
+        *evaluate("2e^2").unwrap(),
+        2.0 * std::f64::consts::E.powi(2),
+        EPS
+    ));
+
+    let err = evaluate("pi2 + 1").unwrap_err();
+    assert!(matches!(err, CalcError::Parse(ParseError::InvalidToken(_))));
+}
+
+#[test]
+fn test_factorial() {
+    assert_eq!(evaluate("0!").unwrap(), 1.0);
+    assert_eq!(evaluate("5!").unwrap(), 120.0);
+    assert_eq!(evaluate("3! + 1").unwrap(), 7.0);
+    assert_eq!(evaluate("2^3!").unwrap(), 64.0); // 2^(3!)
+    assert_eq!(evaluate("-3!").unwrap(), -6.0); // -(3!)
+    assert_eq!(evaluate("(1 + 2)!").unwrap(), 6.0);
+
+    let err = evaluate("2.5!").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+
+    let err = evaluate("(-3)!").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+}
+
+#[test]
+fn test_implicit_multiplication() {
+    assert_eq!(evaluate("2(3+4)").unwrap(), 14.0);
+    assert_eq!(evaluate("3pi").unwrap(), 3.0 * std::f64::consts::PI);
+    assert_eq!(evaluate("2 pi").unwrap(), std::f64::consts::TAU);
+    assert_eq!(evaluate("(1+2)(3+4)").unwrap(), 21.0);
+    assert_eq!(evaluate("(1+2)4").unwrap(), 12.0);
+    assert_eq!(evaluate("2sqrt(9)").unwrap(), 6.0);
+    assert_eq!(evaluate("3!(2)").unwrap(), 12.0);
+    assert_eq!(evaluate("2^2(3)").unwrap(), 12.0); // (2^2)*3
+    assert_eq!(evaluate("-2(3)").unwrap(), -6.0);
+
+    // Два числа подряд не перемножаются неявно
+    let err = evaluate("2 3").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::InvalidExpression(_))
+    ));
+}
+
+#[test]
+fn test_ans() {
+    assert_eq!(evaluate_with_ans("ans", Some(3.0)).unwrap(), 3.0);
+    assert_eq!(evaluate_with_ans("ans * 2", Some(3.0)).unwrap(), 6.0);
+    assert_eq!(evaluate_with_ans("2ans + ans", Some(-1.5)).unwrap(), -4.5);
+    assert_eq!(evaluate_with_ans("1 + 1", Some(10.0)).unwrap(), 2.0);
+
+    // Без предыдущего результата ans не определён
+    let err = evaluate_with_ans("ans + 1", None).unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Eval(EvalError::InvalidExpression(_))
+    ));
+    let err = evaluate("ans").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Некорректное выражение: Нет предыдущего результата для 'ans'"
+    );
+}
+
+#[test]
+fn test_vars() {
+    let vars = HashMap::from([("x".to_string(), 2.0), ("y".to_string(), 3.0)]);
+    assert_eq!(evaluate_with_vars("x*y+1", &vars).unwrap(), 7.0);
+    assert_eq!(evaluate_with_vars("2x + sin(0)", &vars).unwrap(), 4.0);
+
+    let err = evaluate_with_vars("x + z", &vars).unwrap_err();
+    assert_eq!(
+        err,
+        CalcError::Parse(ParseError::UnknownVariable("z".to_string()))
+    );
+    assert_eq!(err.to_string(), "Неизвестная переменная: 'z'");
+}
+
+#[test]
+fn test_nesting_depth() {
+    let nested = |n: usize| format!("{}1{}", "(".repeat(n), ")".repeat(n));
+
+    assert_eq!(evaluate(&nested(256)).unwrap(), 1.0);
+    let err = evaluate(&nested(300)).unwrap_err();
+    assert_eq!(err, CalcError::Parse(ParseError::NestingTooDeep(256)));
+    assert_eq!(
+        err.to_string(),
+        "Слишком глубокая вложенность скобок (больше 256)."
+    );
+}
+
+#[test]
+fn test_sequence() {
+    assert_eq!(
+        evaluate_sequence("1+1; 2*3; 4-1").unwrap(),
+        vec![2.0, 6.0, 3.0]
+    );
+    assert_eq!(evaluate_sequence("max(1, 2)").unwrap(), vec![2.0]);
+
+    // Пустые выражения в последовательности запрещены
+    for input in ["1 + 1;", "1;;2", "; 3", ""] {
+        let err = evaluate_sequence(input).unwrap_err();
+        assert!(
+            matches!(err, CalcError::Parse(ParseError::InvalidExpression(_))),
+            "{}",
+            input
+        );
+    }
+
+    // Ошибка в любом выражении прерывает последовательность
+    let err = evaluate_sequence("1; 1 / 0; 2").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DivideByZero)));
+}
+
+#[test]
+fn test_sequence_with_options() {
+    let options = rpn::EvalOptions {
+        dms: true,
+        lenient: true,
+        ..rpn::EvalOptions::default()
+    };
+    assert_eq!(
+        evaluate_sequence_with("90d30m; (2 + 3", &options).unwrap(),
+        vec![90.5, 5.0]
+    );
+    assert!(evaluate_sequence("90d30m").is_err());
+}
+
+#[test]
+fn test_evaluate_many() {
+    let inputs = [
+        "1 + 1",
+        "1 / 0",
+        "(2 + 3",
+        "max(2, 3) * 4",
+        "",
+        "2^10",
+        "sin(0)",
+    ];
+    let results = evaluate_many(&inputs);
+    assert_eq!(results.len(), inputs.len());
+    for (input, result) in inputs.iter().zip(&results) {
+        assert_eq!(*result, evaluate(input).map(f64::from), "{}", input);
+    }
+    assert!(evaluate_many(&[]).is_empty());
+}
+
+#[test]
+fn test_evaluate_detailed() {
+    assert_eq!(
+        evaluate_detailed("10/4").unwrap(),
+        EvalResult {
+            value: 2.5,
+            display: "2.5".to_string(),
+        }
+    );
+
+    let result = evaluate_detailed("0.1 + 0.2").unwrap();
+    assert!(approx_eq(result.value, 0.3, EPS));
+    assert_eq!(result.display, "0.3");
+
+    assert!(matches!(
+        evaluate_detailed("1 / 0"),
+        Err(CalcError::Eval(EvalError::DivideByZero))
+    ));
+}
+
+#[test]
+fn test_compiled_expr() {
+    let expr = CompiledExpr::compile("2 * (3 + 4)").unwrap();
+    for _ in 0..3 {
+        assert_eq!(expr.eval().unwrap(), 14.0);
+    }
+
+    // Ошибки разбора возникают при компиляции, ошибки вычисления — при eval
+    let err = CompiledExpr::compile("2 * (3 + 4").unwrap_err();
+    assert_eq!(err, CalcError::Parse(ParseError::UnmatchedParensAt(2)));
+    let expr = CompiledExpr::compile("1 / 0").unwrap();
+    assert!(matches!(
+        expr.eval(),
+        Err(CalcError::Eval(EvalError::DivideByZero))
+    ));
+}
+
+#[test]
+fn test_divide_by_zero() {
+    let err = evaluate("1 / 0").unwrap_err();
+    assert_eq!(err.to_string(), "Деление на 0.");
+}
+
+#[test]
+fn test_modulo() {
+    assert_eq!(evaluate("10 % 3").unwrap(), 1.0);
+    assert_eq!(evaluate("2 + 7 % 4 * 2").unwrap(), 8.0); // 2 + ((7%4)*2)
+    let err = evaluate("5 % 0").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DivideByZero)));
+}
+
+#[test]
+fn test_min_max() {
+    assert_eq!(evaluate("max(3,7)").unwrap(), 7.0);
+    assert_eq!(evaluate("min(-1, 2)").unwrap(), -1.0);
+    assert_eq!(evaluate("max(1 + 2, 2 * 2) - 1").unwrap(), 3.0);
+    assert_eq!(evaluate("min(max(1, 5), 3)").unwrap(), 3.0);
+    assert_eq!(evaluate("2max(1, 2)").unwrap(), 4.0);
+
+    let err = evaluate("min(3,7,9)").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Некорректное выражение: Функция 'min' принимает аргументов: 2, передано: 3"
+    );
+    let err = evaluate("max(3)").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::InvalidExpression(_))
+    ));
+    let err = evaluate("1, 2").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::InvalidExpression(_))
+    ));
+}
+
+#[test]
+fn test_gcd_lcm() {
+    assert_eq!(evaluate("gcd(12, 18)").unwrap(), 6.0);
+    assert_eq!(evaluate("lcm(4, 6)").unwrap(), 12.0);
+    assert_eq!(evaluate("gcd(0, 5)").unwrap(), 5.0);
+    assert_eq!(evaluate("lcm(2 + 1, gcd(8, 12))").unwrap(), 12.0);
+
+    let err = evaluate("gcd(2.5, 5)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+    let err = evaluate("lcm(4, 0.5)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+}
+
+#[test]
+fn test_rand() {
+    random::set_seed(7);
+    let first: Vec<f64> = (0..3).map(|_| *evaluate("rand()").unwrap()).collect();
+    random::set_seed(7);
+    let second: Vec<f64> = (0..3).map(|_| *evaluate("rand()").unwrap()).collect();
+    assert_eq!(first, second);
+    assert!(first.iter().all(|x| (0.0..1.0).contains(x)));
+
+    for _ in 0..100 {
+        let x = *evaluate("rand(2, 5)").unwrap();
+        assert!((2.0..5.0).contains(&x));
+    }
+    assert!(evaluate("floor(rand() * 6) + 1 <= 6").unwrap() == 1.0);
+
+    // Границы в обратном порядке — ошибка, а не число из (b, a]
+    let err = evaluate("rand(2, 1)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+    assert_eq!(evaluate("rand(3, 3)").unwrap(), 3.0);
+
+    let err = evaluate("rand(1)").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Некорректное выражение: Функция 'rand' принимает аргументов: 2, передано: 1"
+    );
+    let err = evaluate("sin()").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::InvalidExpression(_))
+    ));
+}
+
+#[test]
+fn test_comparisons() {
+    assert_eq!(evaluate("1 < 2").unwrap(), 1.0);
+    assert_eq!(evaluate("2 <= 2").unwrap(), 1.0);
+    assert_eq!(evaluate("1 > 2").unwrap(), 0.0);
+    assert_eq!(evaluate("3>=4").unwrap(), 0.0);
+    assert_eq!(evaluate("2 + 2 == 4").unwrap(), 1.0);
+    assert_eq!(evaluate("3! != 6").unwrap(), 0.0);
+    // Сравнения связывают слабее побитовых операций
+    assert_eq!(evaluate("5 & 1 == 1").unwrap(), 1.0);
+    assert_eq!(evaluate("(1 < 2) + (2 < 1)").unwrap(), 1.0);
+
+    let err = evaluate("1 < = 2").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::InvalidTokenAt { ch: '=', .. })
+    ));
+}
+
+#[test]
+fn test_bitwise() {
+    assert_eq!(evaluate("6 & 3").unwrap(), 2.0);
+    assert_eq!(evaluate("5 | 2").unwrap(), 7.0);
+    assert_eq!(evaluate("5 ^^ 1").unwrap(), 4.0);
+    assert_eq!(evaluate("0xF0 | 0x0F").unwrap(), 255.0);
+    // Побитовые операции связывают слабее арифметических
+    assert_eq!(evaluate("6 & 3 + 1").unwrap(), 4.0);
+    assert_eq!(evaluate("1 | 2 ^^ 3 & 1").unwrap(), 3.0);
+    // "^^" не путается со степенью
+    assert_eq!(evaluate("2 ^ 3 ^^ 1").unwrap(), 9.0);
+
+    let err = evaluate("2.5 & 1").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DomainError(_))));
+}
+
+#[test]
+fn test_percent() {
+    assert!(approx_eq(*evaluate("15%").unwrap(), 0.15, EPS));
+    assert_eq!(evaluate("200 * 15%").unwrap(), 30.0);
+    assert_eq!(evaluate("(50)%").unwrap(), 0.5);
+    // Процент — просто деление на 100, а не доля от левого операнда
+    assert!(approx_eq(*evaluate("50 + 10%").unwrap(), 50.1, EPS));
+    // Процент и остаток от деления в одном выражении
+    assert_eq!(evaluate("10 % 4 * 50%").unwrap(), 1.0);
+    assert!(approx_eq(*evaluate("20% - 1").unwrap(), -0.8, EPS));
+    // Знак, прижатый к числу, относится к нему: это остаток от деления
+    assert_eq!(evaluate("10 % -3").unwrap(), 1.0);
+}
+
+#[test]
+fn test_error_spans() {
+    assert_eq!(
+        evaluate_spanned("1 / 0"),
+        Err(SpannedError {
+            error: CalcError::Eval(EvalError::DivideByZero),
+            span: 2..3,
+        })
+    );
+
+    let err = evaluate_spanned("2 + (5 % (3 - 3))").unwrap_err();
+    assert_eq!(err.error, CalcError::Eval(EvalError::DivideByZero));
+    assert_eq!(err.span, 7..8);
+
+    let err = evaluate_spanned("1 + sqrt(-4)").unwrap_err();
+    assert!(matches!(
+        err.error,
+        CalcError::Eval(EvalError::DomainError(_))
+    ));
+    assert_eq!(err.span, 4..8);
+
+    // Непарная скобка указывает на себя
+    let err = evaluate_spanned("(1 + 2").unwrap_err();
+    assert_eq!(
+        err.error,
+        CalcError::Parse(ParseError::UnmatchedParensAt(0))
+    );
+    assert_eq!(err.span, 0..1);
+
+    let err = evaluate_spanned("1 + 2)").unwrap_err();
+    assert_eq!(
+        err.error,
+        CalcError::Parse(ParseError::UnmatchedParensAt(3))
+    );
+    assert_eq!(err.span, 5..6);
+
+    // Остальные ошибки разбора относятся ко всему выражению
+    let err = evaluate_spanned("* 2").unwrap_err();
+    assert!(matches!(
+        err.error,
+        CalcError::Parse(ParseError::InvalidExpression(_))
+    ));
+    assert_eq!(err.span, 0..3);
+
+    assert_eq!(evaluate_spanned("2 * 3").unwrap(), 6.0);
+}
+
+#[test]
+fn test_non_finite_results() {
+    let err = evaluate("1e308 * 10").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::NotFinite(_))));
+    assert_eq!(
+        err.to_string(),
+        "Результат не является конечным числом: переполнение"
+    );
+
+    let err = evaluate("1e400").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::NotFinite(_))));
+
+    let err = evaluate("(-8)^(1/3)").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::NotFinite(_))));
+
+    // Явное деление на ноль по-прежнему даёт DivideByZero
+    let err = evaluate("0 / 0").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DivideByZero)));
+}
+
+#[test]
+fn test_invalid_tokens() {
+    let err = evaluate("2 + abc").unwrap_err();
+    assert!(err.to_string().contains("Некорректный символ"));
+
+    let err = evaluate("1.2.3").unwrap_err();
+    assert!(err.to_string().contains("Некорректное число"));
+
+    let err = evaluate("1 + 2 *").unwrap_err();
+    assert!(err.to_string().contains("Некорректное выражение"));
+}
+
+#[test]
+fn test_unmatched_parens() {
+    let err = evaluate("(2 + 3").unwrap_err();
+    assert_eq!(err.to_string(), "Не совпадают скобки (токен 0).");
+
+    let err = evaluate("2 + 3)").unwrap_err();
+    assert_eq!(err.to_string(), "Не совпадают скобки (токен 3).");
+
+    let err = evaluate("((2 + 3) * 4").unwrap_err();
+    assert_eq!(err.to_string(), "Не совпадают скобки (токен 0).");
+}
+
+#[test]
+fn test_mixed_operations() {
+    assert_eq!(evaluate("2 + 3 * (4 - 1)^2").unwrap(), 29.0); // 2 + 3*(3^2)
+    assert_eq!(evaluate("10 / (2 + 3) * 4").unwrap(), 8.0); // (10/5)*4
+    assert_eq!(evaluate("-2^3").unwrap(), -8.0); // -(2^3)
+    assert_eq!(evaluate("(-2)^3").unwrap(), -8.0); // (-2)^3
+}
+
+#[test]
+fn test_large_numbers() {
+    assert_eq!(evaluate("1234567890 + 987654321").unwrap(), 2222222211.0);
+    assert_eq!(evaluate("1e6 * 1e3").unwrap(), 1_000_000_000.0); // 10^6 * 10^3
+    assert_eq!(evaluate("2^10").unwrap(), 1024.0);
+}
+
+#[test]
+fn test_digit_separators() {
+    assert_eq!(evaluate("1_000").unwrap(), 1000.0);
+    assert_eq!(evaluate("1_000_000 + 500").unwrap(), 1_000_500.0);
+
+    assert!(matches!(
+        evaluate("1__0").unwrap_err(),
+        CalcError::Parse(ParseError::InvalidToken(_))
+    ));
+    assert!(matches!(
+        evaluate("5_").unwrap_err(),
+        CalcError::Parse(ParseError::InvalidToken(_))
+    ));
+    assert!(matches!(
+        evaluate("_5").unwrap_err(),
+        CalcError::Parse(ParseError::InvalidTokenAt { ch: '_', pos: 0 })
+    ));
+
+    // Пробел разрядами не считается: "1 000" — два числа без оператора
+    assert!(matches!(
+        evaluate("1 000").unwrap_err(),
+        CalcError::Parse(ParseError::InvalidExpression(_))
+    ));
+}
+
+#[test]
+fn test_radix_literals() {
+    assert_eq!(evaluate("0xFF + 1").unwrap(), 256.0);
+    assert_eq!(evaluate("0b1010 * 2").unwrap(), 20.0);
+    assert_eq!(evaluate("0x10 - 0b11").unwrap(), 13.0);
+}
+
+#[test]
+fn test_edge_cases() {
+    // Пустое выражение
+    let err = evaluate("").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Eval(EvalError::InvalidExpression(_))
+    ));
+    assert_eq!(
+        err.to_string(),
+        "Некорректное выражение: Стек пуст после вычислений"
+    );
+
+    // Незакрытая скобка
+    let err = evaluate(")").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::UnmatchedParensAt(0))
+    ));
+    assert_eq!(err.to_string(), "Не совпадают скобки (токен 0).");
+
+    // Некорректный оператор
+    let err = evaluate("1 + 2 * / 3").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Eval(EvalError::InvalidExpression(_))
+    ));
+    assert_eq!(
+        err.to_string(),
+        "Некорректное выражение: Недостаточно операндов для операции '+'"
+    );
+
+    // Деление на ноль
+    let err = evaluate("1 / 0").unwrap_err();
+    assert!(matches!(err, CalcError::Eval(EvalError::DivideByZero)));
+    assert_eq!(err.to_string(), "Деление на 0.");
+
+    // Недостаточно операндов для операции
+    let err = evaluate("1 +").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::InvalidExpression(_))
+    ));
+    assert_eq!(
+        err.to_string(),
+        "Некорректное выражение: Выражение заканчивается оператором '+'"
+    );
+
+    // Унарная операция без операнда
+    let err = evaluate("-").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Eval(EvalError::InvalidExpression(_))
+    ));
+    assert_eq!(
+        err.to_string(),
+        "Некорректное выражение: Унарный минус требует одного операнда"
+    );
+
+    // Лишние числа в стеке
+    let err = evaluate("1 2 + 2").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::InvalidExpression(_))
+    ));
+    assert_eq!(
+        err.to_string(),
+        "Некорректное выражение: Пропущен оператор между операндами в позиции 2"
+    );
+
+    let err = evaluate("3 4 5").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::InvalidExpression(_))
+    ));
+
+    // Некорректный токен
+    let err = evaluate("abc").unwrap_err();
+    assert!(matches!(err, CalcError::Parse(ParseError::InvalidToken(_))));
+    assert_eq!(
+        err.to_string(),
+        "Некорректный символ: Неизвестный идентификатор: 'abc'"
+    );
+
+    // Некорректный символ с позицией
+    let err = evaluate("1 + 2 $ 3").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::InvalidTokenAt { ch: '$', pos: 6 })
+    ));
+    assert_eq!(err.to_string(), "Некорректный символ '$' в позиции 6");
+
+    // Несколько точек в числе
+    let err = evaluate("1.2.3").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::InvalidNumber { .. })
+    ));
+    assert_eq!(err.to_string(), "Некорректное число: '1.2.3'.");
+
+    // Незакрытые скобки в начале выражения
+    let err = evaluate("((2 + 3)").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::UnmatchedParensAt(0))
+    ));
+    assert_eq!(err.to_string(), "Не совпадают скобки (токен 0).");
+
+    // Лишние закрывающие скобки
+    let err = evaluate("2 + 3))").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::UnmatchedParensAt(3))
+    ));
+    assert_eq!(err.to_string(), "Не совпадают скобки (токен 3).");
+
+    // Оператор в конце выражения без операндов
+    let err = evaluate("5 + 2 *").unwrap_err();
+    assert!(matches!(
+        err,
+        CalcError::Parse(ParseError::InvalidExpression(_))
+    ));
+    assert_eq!(
+        err.to_string(),
+        "Некорректное выражение: Выражение заканчивается оператором '*'"
+    );
+
+    // Бинарный оператор в начале выражения
+    let err = evaluate("* 3").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Некорректное выражение: Выражение начинается с оператора '*'"
+    );
+    assert_eq!(evaluate("-3").unwrap(), -3.0);
+}
+
+#[test]
+fn test_validate() {
+    // Синтаксис корректен, деление на ноль не вычисляется
+    assert_eq!(validate("1/0"), Ok(()));
+    assert_eq!(validate("sqrt(-1) + max(1, 2)"), Ok(()));
+
+    assert!(matches!(
+        validate("1/").unwrap_err(),
+        ParseError::InvalidExpression(_)
+    ));
+    assert!(matches!(
+        validate("(1+2").unwrap_err(),
+        ParseError::UnmatchedParensAt(_)
+    ));
+    assert!(validate("min(1)").is_err());
+    assert!(validate("2 @ 3").is_err());
+
+    // Длинная цепочка унарных минусов не переполняет стек
+    let deep = "-".repeat(1_000_000) + "1";
+    assert_eq!(validate(&deep), Ok(()));
+    assert_eq!(*evaluate(&deep).unwrap(), 1.0);
+}
+
+#[test]
+fn test_error_categories() {
+    // Ошибки разбора возвращает лексер, ошибки вычисления — вычисление ОПЗ
+    let err = parser::tokenize("2 @ 3").unwrap_err();
+    assert_eq!(err, ParseError::InvalidTokenAt { ch: '@', pos: 2 });
+    let err = rpn::to_rpn(parser::tokenize("(1 + 2").unwrap()).unwrap_err();
+    assert_eq!(err, ParseError::UnmatchedParens);
+
+    let rpn = rpn::to_rpn(parser::tokenize("1 / 0").unwrap()).unwrap();
+    assert_eq!(rpn::eval_rpn(&rpn), Err(EvalError::DivideByZero));
+    let rpn = rpn::to_rpn(parser::tokenize("sqrt(-1)").unwrap()).unwrap();
+    assert!(matches!(
+        rpn::eval_rpn(&rpn),
+        Err(EvalError::DomainError(_))
+    ));
+
+    // Текст ошибки тот же, что у общего CalcError
+    for input in ["2 @ 3", "1 / 0", "sqrt(-1)"] {
+        let combined = evaluate(input).unwrap_err();
+        let specific = match parser::tokenize(input) {
+            Err(e) => e.to_string(),
+            Ok(tokens) => match rpn::to_rpn(tokens) {
+                Err(e) => e.to_string(),
+                Ok(rpn) => rpn::eval_rpn(&rpn).unwrap_err().to_string(),
+            },
+        };
+        assert_eq!(combined.to_string(), specific);
+    }
+    assert_eq!(
+        CalcError::from(EvalError::DivideByZero),
+        CalcError::Eval(EvalError::DivideByZero)
+    );
+}
+
+#[test]
+fn test_number_result() {
+    let result = evaluate("0.1 + 0.2").unwrap();
+    assert_eq!(result.to_string(), "0.3");
+    assert_eq!(*result, 0.1 + 0.2);
+    assert_eq!(result.sqrt(), (0.1f64 + 0.2).sqrt());
+
+    let result = evaluate("10 / 4").unwrap();
+    assert_eq!(result, Number(2.5));
+    assert_eq!(result, 2.5);
+    assert_eq!(f64::from(result) * 2.0, 5.0);
+    assert_eq!(format!("{}", evaluate("2 * 3").unwrap()), "6");
+}
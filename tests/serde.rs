@@ -0,0 +1,72 @@
+#![cfg(feature = "serde")]
+
+use calculator::{
+    error::{CalcError, EvalError, ParseError},
+    parser::{Token, tokenize},
+};
+
+#[test]
+fn test_tokens_round_trip() {
+    let tokens = tokenize("max(2.5, -3) + sin(pi) * 4!").unwrap();
+    let json = serde_json::to_string(&tokens).unwrap();
+    let restored: Vec<Token> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, tokens);
+}
+
+#[test]
+fn test_token_json_shape() {
+    assert_eq!(
+        serde_json::to_string(&Token::Number(2.0)).unwrap(),
+        r#"{"Number":2.0}"#
+    );
+    assert_eq!(serde_json::to_string(&Token::Plus).unwrap(), r#""Plus""#);
+}
+
+#[test]
+fn test_error_serializes_tagged() {
+    assert_eq!(
+        serde_json::to_string(&EvalError::DivideByZero).unwrap(),
+        r#"{"type":"DivideByZero"}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&EvalError::DomainError("sqrt(-1)".to_string())).unwrap(),
+        r#"{"type":"DomainError","value":"sqrt(-1)"}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&ParseError::InvalidTokenAt { ch: '@', pos: 2 }).unwrap(),
+        r#"{"type":"InvalidTokenAt","value":{"ch":"@","pos":2}}"#
+    );
+
+    // CalcError добавляет категорию ошибки: разбор или вычисление
+    let error = CalcError::Eval(EvalError::DivideByZero);
+    let json = serde_json::to_string(&error).unwrap();
+    assert_eq!(json, r#"{"type":"Eval","value":{"type":"DivideByZero"}}"#);
+    assert_eq!(serde_json::from_str::<CalcError>(&json).unwrap(), error);
+
+    // Ошибка разбора числа сохраняется текстом исходной ошибки
+    let error = CalcError::Parse(ParseError::InvalidNumber {
+        literal: "1.2.3".to_string(),
+        source: "1.2.3".parse::<f64>().unwrap_err(),
+    });
+    let json = serde_json::to_string(&error).unwrap();
+    assert_eq!(
+        json,
+        r#"{"type":"Parse","value":{"type":"InvalidNumber","value":{"literal":"1.2.3","source":"invalid float literal"}}}"#
+    );
+    assert_eq!(serde_json::from_str::<CalcError>(&json).unwrap(), error);
+
+    let error: CalcError =
+        serde_json::from_str(r#"{"type":"Parse","value":{"type":"InvalidToken","value":"abc"}}"#)
+            .unwrap();
+    assert_eq!(
+        error,
+        CalcError::Parse(ParseError::InvalidToken("abc".to_string()))
+    );
+}
+
+#[test]
+fn test_parse_error_round_trip() {
+    let error = tokenize("2 @ 3").unwrap_err();
+    let json = serde_json::to_string(&error).unwrap();
+    assert_eq!(serde_json::from_str::<ParseError>(&json).unwrap(), error);
+}
@@ -0,0 +1,223 @@
+use calculator::{
+    Session,
+    error::{CalcError, EvalError, ParseError},
+    rpn::{AngleMode, EvalOptions},
+    testing::approx_eq,
+};
+
+#[test]
+fn test_assignment() {
+    let mut session = Session::new();
+    assert_eq!(session.eval("x = 5").unwrap(), 5.0);
+    assert_eq!(session.variable("x"), Some(5.0));
+    assert_eq!(session.eval("y = x * 2 + 1").unwrap(), 11.0);
+    assert_eq!(session.variable("y"), Some(11.0));
+}
+
+#[test]
+fn test_comparison_is_not_assignment() {
+    let mut session = Session::new();
+    session.eval("x = 5").unwrap();
+    assert_eq!(session.eval("x == 5").unwrap(), 1.0);
+    assert_eq!(session.eval("x != 5").unwrap(), 0.0);
+    assert_eq!(session.eval("y = x >= 5").unwrap(), 1.0);
+    assert_eq!(session.variable("y"), Some(1.0));
+    assert_eq!(session.variable("x"), Some(5.0));
+}
+
+#[test]
+fn test_variable_reuse() {
+    let mut session = Session::new();
+    session.eval("x = 5").unwrap();
+    assert_eq!(session.eval("x * 2").unwrap(), 10.0);
+    assert_eq!(session.eval("2x + x").unwrap(), 15.0);
+    assert_eq!(session.eval("x(x - 1)").unwrap(), 20.0);
+    assert_eq!(session.eval("sqrt(x + 4)").unwrap(), 3.0);
+}
+
+#[test]
+fn test_variable_redefinition() {
+    let mut session = Session::new();
+    session.eval("x = 5").unwrap();
+    assert_eq!(session.eval("x = x + 1").unwrap(), 6.0);
+    assert_eq!(session.eval("x").unwrap(), 6.0);
+}
+
+#[test]
+fn test_reserved_names() {
+    let mut session = Session::new();
+    for input in ["pi = 3", "e = 1", "sin = 2", "ans = 4"] {
+        let err = session.eval(input).unwrap_err();
+        assert!(matches!(
+            err,
+            CalcError::Parse(ParseError::InvalidExpression(_))
+        ));
+    }
+    assert_eq!(
+        session.eval("pi = 3").unwrap_err().to_string(),
+        "Некорректное выражение: Имя 'pi' зарезервировано"
+    );
+    assert_eq!(session.eval("pi").unwrap(), std::f64::consts::PI);
+}
+
+#[test]
+fn test_invalid_variable_name() {
+    let mut session = Session::new();
+    for input in ["2x = 1", " = 1", "x y = 1", "x+1 = 2"] {
+        let err = session.eval(input).unwrap_err();
+        assert!(matches!(
+            err,
+            CalcError::Parse(ParseError::InvalidExpression(_))
+        ));
+    }
+}
+
+#[test]
+fn test_undefined_variable() {
+    let mut session = Session::new();
+    let err = session.eval("z + 1").unwrap_err();
+    assert_eq!(
+        err,
+        CalcError::Parse(ParseError::UnknownVariable("z".to_string()))
+    );
+
+    // Неудачное присваивание не создаёт переменную
+    assert!(session.eval("z = 1 / 0").is_err());
+    assert_eq!(session.variable("z"), None);
+}
+
+#[test]
+fn test_session_ans() {
+    let mut session = Session::new();
+    assert_eq!(session.ans(), None);
+    session.eval("2 + 3").unwrap();
+    assert_eq!(session.eval("ans * 2").unwrap(), 10.0);
+    assert_eq!(session.ans(), Some(10.0));
+
+    // Ошибка не сбрасывает ans
+    assert!(session.eval("1 / 0").is_err());
+    assert_eq!(session.ans(), Some(10.0));
+}
+
+#[test]
+fn test_angle_mode() {
+    let mut session = Session::new();
+    assert_eq!(session.angle_mode(), AngleMode::Radians);
+    assert!(approx_eq(session.eval("sin(90)").unwrap(), 0.894, 1e-3));
+
+    session.set_angle_mode(AngleMode::Degrees);
+    assert!(approx_eq(session.eval("sin(90)").unwrap(), 1.0, 1e-12));
+    assert!(approx_eq(session.eval("cos(60)").unwrap(), 0.5, 1e-12));
+    assert!(approx_eq(session.eval("sec(60)").unwrap(), 2.0, 1e-12));
+    assert!(session.eval("csc(180)").is_err());
+    assert!(approx_eq(session.eval("asin(1)").unwrap(), 90.0, 1e-12));
+    assert!(approx_eq(session.eval("atan(1)").unwrap(), 45.0, 1e-12));
+    assert!(approx_eq(session.eval("atan2(1, 1)").unwrap(), 45.0, 1e-12));
+    assert!(approx_eq(
+        session.eval("atan2(0, -1)").unwrap(),
+        180.0,
+        1e-12
+    ));
+    // Гиперболические функции единицы измерения углов не учитывают
+    assert!(approx_eq(
+        session.eval("cosh(1)").unwrap(),
+        1.5430806348152437,
+        1e-12
+    ));
+
+    session.set_angle_mode(AngleMode::Radians);
+    assert!(approx_eq(session.eval("sin(pi / 2)").unwrap(), 1.0, 1e-12));
+}
+
+#[test]
+fn test_dms_angles() {
+    let mut session = Session::new();
+    assert!(session.eval("90d30m").is_err());
+
+    session.set_dms(true);
+    assert_eq!(session.eval("90d30m == 90.5").unwrap(), 1.0);
+    assert_eq!(session.eval("1d").unwrap(), 1.0);
+
+    session.set_angle_mode(AngleMode::Degrees);
+    assert!(approx_eq(session.eval("sin(30d)").unwrap(), 0.5, 1e-12));
+}
+
+#[test]
+fn test_lenient_parens() {
+    let mut session = Session::new();
+    assert!(matches!(
+        session.eval("(2+3").unwrap_err(),
+        CalcError::Parse(ParseError::UnmatchedParensAt(0))
+    ));
+
+    session.set_lenient(true);
+    assert_eq!(session.eval("(2+3").unwrap(), 5.0);
+    assert_eq!(session.eval("2 * (1 + sqrt(16").unwrap(), 10.0);
+    // Лишние закрывающие скобки по-прежнему ошибка
+    assert!(matches!(
+        session.eval("2+3)").unwrap_err(),
+        CalcError::Parse(ParseError::UnmatchedParensAt(3))
+    ));
+}
+
+#[test]
+fn test_history_refs() {
+    let mut session = Session::new();
+    assert!(session.eval("$1").is_err());
+
+    assert_eq!(session.eval("2 + 3").unwrap(), 5.0);
+    assert_eq!(session.eval("x = 4").unwrap(), 4.0);
+    assert!(session.eval("1 / 0").is_err());
+    // Строки с ошибкой тоже нумеруются, но результата у них нет
+    assert_eq!(session.eval("$2 * $3").unwrap(), 20.0);
+    assert!(session.eval("$4").is_err());
+    assert_eq!(session.eval("$5 - 1").unwrap(), 19.0);
+    assert_eq!(
+        session.results(),
+        &[
+            None,
+            Some(5.0),
+            Some(4.0),
+            None,
+            Some(20.0),
+            None,
+            Some(19.0)
+        ]
+    );
+
+    // Ссылка за пределами истории — ошибка
+    assert!(matches!(
+        session.eval("$9").unwrap_err(),
+        CalcError::Eval(EvalError::InvalidExpression(_))
+    ));
+    assert!(calculator::evaluate("$1").is_err());
+
+    // Результаты, вычисленные вне сеанса, занимают следующие номера
+    let mut session = Session::new();
+    session.push_result(Some(7.0));
+    session.push_result(None);
+    assert_eq!(session.eval("$1 + 1").unwrap(), 8.0);
+    assert!(session.eval("$2").is_err());
+}
+
+#[test]
+fn test_integers_only() {
+    let mut session = Session::with_options(EvalOptions {
+        integers_only: true,
+        ..EvalOptions::default()
+    });
+    assert_eq!(session.eval("6 / 2").unwrap(), 3.0);
+    assert!(matches!(
+        session.eval("7 / 2").unwrap_err(),
+        CalcError::Eval(EvalError::DomainError(_))
+    ));
+    assert!(session.eval("sqrt(2)").is_err());
+    assert!(matches!(
+        session.eval("2.5").unwrap_err(),
+        CalcError::Eval(EvalError::DomainError(_))
+    ));
+    assert!(matches!(
+        session.eval("pi").unwrap_err(),
+        CalcError::Eval(EvalError::DomainError(_))
+    ));
+}